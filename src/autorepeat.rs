@@ -0,0 +1,65 @@
+//! Repeating an action while its key is held: Delayed Auto Shift (DAS) and Auto Repeat Rate (ARR).
+//!
+//! The action happens once when the key goes down, again after the delay, and then once every interval
+//! for as long as the key is held. Everything is worked out from the time the key has been held, not by
+//! counting frames, so it repeats at the same rate at 30 or 240 frames a second. At low frame rates one
+//! frame can owe several repeats.
+
+use std::time::Duration;
+
+/// The auto-repeat state for one key
+#[derive(Debug, Clone)]
+pub struct AutoRepeat {
+    delay: Duration,
+    interval: Duration,
+    held: Option<Duration>, // how long the key has been down, None if it is up
+}
+
+impl AutoRepeat {
+    /// A key that repeats `delay` after it is pressed, then every `interval`.
+    /// A zero interval repeats as often as it is asked to (eg straight to the wall)
+    pub fn new(delay: Duration, interval: Duration) -> AutoRepeat {
+        AutoRepeat {
+            delay,
+            interval,
+            held: None,
+        }
+    }
+
+    /// Change the repeat interval, eg when the soft drop speeds up with the level
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    /// Update with the state of the key and the time since the last update.
+    /// Returns how many times the action should happen now (including the first press)
+    pub fn update(&mut self, pressed: bool, elapsed: Duration) -> usize {
+        if !pressed {
+            self.held = None;
+            return 0;
+        }
+
+        match self.held {
+            None => {
+                self.held = Some(Duration::ZERO);
+                1
+            }
+            Some(before) => {
+                let now = before + elapsed;
+                self.held = Some(now);
+                self.repeats(now) - self.repeats(before)
+            }
+        }
+    }
+
+    /// How many repeats there have been (not counting the first press) after holding the key this long
+    fn repeats(&self, held: Duration) -> usize {
+        if held < self.delay {
+            0
+        } else if self.interval.is_zero() {
+            usize::MAX / 2 // Everything at once, whatever the caller can use
+        } else {
+            ((held - self.delay).as_nanos() / self.interval.as_nanos()) as usize + 1
+        }
+    }
+}
@@ -0,0 +1,348 @@
+//! Which keys and gamepad buttons do what: the input map from them to controls, and the file it is kept in.
+//!
+//! Keys and buttons are named as strings (eg "Left", "Space", "J", or "DPadLeft", "South"), so nothing in here
+//! needs to know about any particular engine's codes: the view checks the names it understands, and turns them
+//! into its own codes.
+//!
+//! The file has one line per control, with the keys for it, and the same again for gamepad buttons:
+//! ```toml
+//! [keys]
+//! MoveLeft = ["A", "Left"]
+//! MoveRight = ["D", "Right"]
+//! Pause = ["P", "Escape"]
+//!
+//! [buttons]
+//! Hold = ["LeftTrigger", "RightTrigger"]
+//! ```
+//! Controls the file doesn't mention keep their defaults. A key (or a button) can only do one thing, so a key
+//! bound to two controls is a conflict, and the file is refused. A key and a button can do the same thing.
+
+use crate::game::Action;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Something a key can be bound to: an action for the current tetromino, or something for the game as a whole
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Control {
+    Play(Action),
+    Pause,
+    Restart,
+    Quit,
+    Ghost,
+    Bindings,
+    GravityUp,
+    GravityDown,
+}
+
+impl Control {
+    /// Every control, in the order they are listed on the bindings screen
+    pub const ALL: [Control; 14] = [
+        Control::Play(Action::MoveLeft),
+        Control::Play(Action::MoveRight),
+        Control::Play(Action::SoftDrop),
+        Control::Play(Action::HardDrop),
+        Control::Play(Action::RotateClockwise),
+        Control::Play(Action::RotateAntiClockwise),
+        Control::Play(Action::Hold),
+        Control::Pause,
+        Control::Restart,
+        Control::Quit,
+        Control::Ghost,
+        Control::Bindings,
+        Control::GravityUp,
+        Control::GravityDown,
+    ];
+
+    /// The name used for this control in files (the same as the action's name for actions)
+    pub fn name(self) -> &'static str {
+        match self {
+            Control::Play(action) => action.name(),
+            Control::Pause => "Pause",
+            Control::Restart => "Restart",
+            Control::Quit => "Quit",
+            Control::Ghost => "Ghost",
+            Control::Bindings => "Bindings",
+            Control::GravityUp => "GravityUp",
+            Control::GravityDown => "GravityDown",
+        }
+    }
+
+    /// The control with this name, if there is one
+    pub fn from_name(name: &str) -> Option<Control> {
+        Control::ALL.iter().copied().find(|control| control.name() == name)
+    }
+
+    /// The keys it has always had
+    fn default_keys(self) -> &'static [&'static str] {
+        match self {
+            Control::Play(Action::MoveLeft) => &["J", "Left"],
+            Control::Play(Action::MoveRight) => &["L", "Right"],
+            Control::Play(Action::SoftDrop) => &["K", "Down"],
+            Control::Play(Action::HardDrop) => &["Space"],
+            Control::Play(Action::RotateClockwise) => &["X"],
+            Control::Play(Action::RotateAntiClockwise) => &["Z"],
+            Control::Play(Action::Hold) => &["C"],
+            Control::Pause => &["P", "Escape"],
+            Control::Restart => &["R"],
+            Control::Quit => &["Q"],
+            Control::Ghost => &["G"],
+            Control::Bindings => &["B"],
+            Control::GravityUp => &["Equals", "NumpadAdd"],
+            Control::GravityDown => &["Minus", "NumpadSubtract"],
+        }
+    }
+
+    /// The gamepad buttons it starts with: the d-pad moves and drops, the face buttons turn, the shoulders hold
+    fn default_buttons(self) -> &'static [&'static str] {
+        match self {
+            Control::Play(Action::MoveLeft) => &["DPadLeft"],
+            Control::Play(Action::MoveRight) => &["DPadRight"],
+            Control::Play(Action::SoftDrop) => &["DPadDown"],
+            Control::Play(Action::HardDrop) => &["DPadUp"],
+            Control::Play(Action::RotateClockwise) => &["South"],
+            Control::Play(Action::RotateAntiClockwise) => &["East"],
+            Control::Play(Action::Hold) => &["LeftTrigger", "RightTrigger"],
+            Control::Pause => &["Start"],
+            Control::Restart => &["Select"],
+            Control::Quit | Control::Ghost | Control::Bindings | Control::GravityUp | Control::GravityDown => &[],
+        }
+    }
+}
+
+/// Where a binding comes from
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Device {
+    Keyboard,
+    Gamepad,
+}
+
+/// The keys and gamepad buttons for every control
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bindings {
+    keys: Vec<Vec<String>>,    // in the same order as Control::ALL
+    buttons: Vec<Vec<String>>, // likewise
+}
+
+impl Default for Bindings {
+    fn default() -> Bindings {
+        let names = |defaults: fn(Control) -> &'static [&'static str]| {
+            Control::ALL
+                .iter()
+                .map(|&control| defaults(control).iter().map(|name| name.to_string()).collect())
+                .collect()
+        };
+        Bindings {
+            keys: names(Control::default_keys),
+            buttons: names(Control::default_buttons),
+        }
+    }
+}
+
+impl Bindings {
+    /// The keys for a control
+    pub fn keys(&self, control: Control) -> &[String] {
+        &self.keys[Bindings::index(control)]
+    }
+
+    /// The gamepad buttons for a control
+    pub fn buttons(&self, control: Control) -> &[String] {
+        &self.buttons[Bindings::index(control)]
+    }
+
+    /// The control a key is bound to, if any
+    pub fn control(&self, key: &str) -> Option<Control> {
+        self.find(Device::Keyboard, key)
+    }
+
+    /// The control a gamepad button is bound to, if any
+    pub fn button_control(&self, button: &str) -> Option<Control> {
+        self.find(Device::Gamepad, button)
+    }
+
+    /// Add a key to a control. If the key already does something else it isn't added, and the other control is returned
+    pub fn bind(&mut self, control: Control, key: &str) -> Result<(), Control> {
+        self.add(Device::Keyboard, control, key)
+    }
+
+    /// Add a gamepad button to a control, in the same way as `bind`
+    pub fn bind_button(&mut self, control: Control, button: &str) -> Result<(), Control> {
+        self.add(Device::Gamepad, control, button)
+    }
+
+    /// Take all the keys and buttons off a control
+    pub fn clear(&mut self, control: Control) {
+        self.keys[Bindings::index(control)].clear();
+        self.buttons[Bindings::index(control)].clear();
+    }
+
+    /// Every key (and every button) that is bound to more than one control, with the first two controls it is bound to
+    pub fn conflicts(&self) -> Vec<(String, Control, Control)> {
+        let mut conflicts = Vec::new();
+        for device in [Device::Keyboard, Device::Gamepad] {
+            for (index, &control) in Control::ALL.iter().enumerate() {
+                for name in self.names(device, control) {
+                    let later = Control::ALL[index + 1..]
+                        .iter()
+                        .find(|&&other| self.names(device, other).contains(name));
+                    if let Some(&other) = later {
+                        conflicts.push((name.clone(), control, other));
+                    }
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// Check every key and button is one the view knows (`known_key`, `known_button`) and none does two things
+    pub fn check(
+        &self,
+        known_key: impl Fn(&str) -> bool,
+        known_button: impl Fn(&str) -> bool,
+    ) -> Result<(), BindingsError> {
+        for &control in Control::ALL.iter() {
+            if let Some(key) = self.keys(control).iter().find(|key| !known_key(key)) {
+                return Err(BindingsError::UnknownKey(key.clone(), control));
+            }
+            if let Some(button) = self.buttons(control).iter().find(|button| !known_button(button)) {
+                return Err(BindingsError::UnknownButton(button.clone(), control));
+            }
+        }
+        match self.conflicts().into_iter().next() {
+            Some((name, first, second)) => Err(BindingsError::Conflict(name, first, second)),
+            None => Ok(()),
+        }
+    }
+
+    /// Read the bindings from a file. Any key names are accepted here, see `check`
+    pub fn load(path: &Path) -> Result<Bindings, BindingsError> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    /// Write the bindings to a file
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    fn index(control: Control) -> usize {
+        Control::ALL.iter().position(|&other| other == control).unwrap_or_default()
+    }
+
+    fn names(&self, device: Device, control: Control) -> &[String] {
+        match device {
+            Device::Keyboard => self.keys(control),
+            Device::Gamepad => self.buttons(control),
+        }
+    }
+
+    fn names_mut(&mut self, device: Device, control: Control) -> &mut Vec<String> {
+        let index = Bindings::index(control);
+        match device {
+            Device::Keyboard => &mut self.keys[index],
+            Device::Gamepad => &mut self.buttons[index],
+        }
+    }
+
+    fn find(&self, device: Device, name: &str) -> Option<Control> {
+        Control::ALL.iter().copied().find(|&control| self.names(device, control).iter().any(|bound| bound == name))
+    }
+
+    fn add(&mut self, device: Device, control: Control, name: &str) -> Result<(), Control> {
+        match self.find(device, name) {
+            Some(other) if other != control => Err(other),
+            Some(_same) => Ok(()),
+            None => {
+                self.names_mut(device, control).push(name.to_string());
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The layout of the file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BindingsFile {
+    #[serde(default)]
+    keys: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    buttons: BTreeMap<String, Vec<String>>,
+}
+
+impl fmt::Display for Bindings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Written in Control::ALL order (rather than a BTreeMap's alphabetical order) to match the bindings screen
+        for (device, section) in [(Device::Keyboard, "[keys]"), (Device::Gamepad, "\n[buttons]")] {
+            writeln!(f, "{}", section)?;
+            for &control in Control::ALL.iter() {
+                let names: Vec<String> = self.names(device, control).iter().map(|name| format!("{:?}", name)).collect();
+                writeln!(f, "{} = [{}]", control.name(), names.join(", "))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Bindings {
+    type Err = BindingsError;
+
+    fn from_str(text: &str) -> Result<Bindings, BindingsError> {
+        let file: BindingsFile = toml::from_str(text).map_err(|error| BindingsError::Parse(error.to_string()))?;
+        let mut bindings = Bindings::default();
+        for (device, section) in [(Device::Keyboard, file.keys), (Device::Gamepad, file.buttons)] {
+            for (name, names) in section {
+                let control = Control::from_name(&name).ok_or(BindingsError::UnknownControl(name))?;
+                *bindings.names_mut(device, control) = names;
+            }
+        }
+        match bindings.conflicts().into_iter().next() {
+            Some((key, first, second)) => Err(BindingsError::Conflict(key, first, second)),
+            None => Ok(bindings),
+        }
+    }
+}
+
+/// Why bindings couldn't be used
+#[derive(Debug)]
+pub enum BindingsError {
+    /// The file couldn't be read at all
+    Io(io::Error),
+    /// The file isn't TOML, or isn't laid out as a bindings file (the message says where)
+    Parse(String),
+    /// There is no control with this name
+    UnknownControl(String),
+    /// A key name the view doesn't know, and the control it is for
+    UnknownKey(String, Control),
+    /// A gamepad button name the view doesn't know, and the control it is for
+    UnknownButton(String, Control),
+    /// A key (or button) bound to two controls
+    Conflict(String, Control, Control),
+}
+
+impl fmt::Display for BindingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BindingsError::Io(error) => write!(f, "{}", error),
+            BindingsError::Parse(message) => write!(f, "{}", message),
+            BindingsError::UnknownControl(name) => write!(f, "there is no control called '{}'", name),
+            BindingsError::UnknownKey(key, control) => write!(f, "unknown key '{}' for {}", key, control.name()),
+            BindingsError::UnknownButton(button, control) => {
+                write!(f, "unknown gamepad button '{}' for {}", button, control.name())
+            }
+            BindingsError::Conflict(key, first, second) => {
+                write!(f, "{} is bound to both {} and {}", key, first.name(), second.name())
+            }
+        }
+    }
+}
+
+impl std::error::Error for BindingsError {}
+
+impl From<io::Error> for BindingsError {
+    fn from(error: io::Error) -> BindingsError {
+        BindingsError::Io(error)
+    }
+}
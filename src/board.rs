@@ -0,0 +1,160 @@
+/// An empty cell
+pub const OPEN: i8 = 0;
+
+/// A cell occupied by the current tetromino
+pub const CURRENT: i8 = 1;
+
+/// A cell occupied by a block on the heap
+pub const HEAP: i8 = 2;
+
+/// The playing grid, including the buffer rows at the top where new tetrominoes appear.
+///
+/// Row 0 is at the top, so a falling block has an increasing y.
+///
+/// Garbage (see `raise`) is on the heap like any other block, but the board remembers which rows came in as
+/// garbage, so that clearing them can be counted.
+#[derive(Debug, Clone)]
+pub struct Board {
+    width: i32,
+    height: i32,
+    occupation: Vec<i8>, // [(y * width) + x] = occupation (OPEN, CURRENT or HEAP)
+    garbage: Vec<bool>,  // [y] = did the row come in as garbage
+}
+
+impl Board {
+    /// An empty board `width` blocks wide and `height` blocks high (including any buffer rows)
+    pub fn new(width: i32, height: i32) -> Board {
+        Board {
+            width,
+            height,
+            occupation: vec![OPEN; (width * height) as usize],
+            garbage: vec![false; height as usize],
+        }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    /// The raw occupation array, one row after another from the top
+    pub fn occupation(&self) -> &[i8] {
+        &self.occupation
+    }
+
+    /// Is (x, y) inside the board?
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= 0 && x < self.width && y >= 0 && y < self.height
+    }
+
+    /// The occupation of a cell. Anything outside the board counts as heap
+    pub fn get(&self, x: i32, y: i32) -> i8 {
+        if self.contains(x, y) {
+            self.occupation[self.address(x, y)]
+        } else {
+            HEAP
+        }
+    }
+
+    /// Set the occupation of a cell. Panics if (x, y) is outside the board
+    pub fn set(&mut self, x: i32, y: i32, value: i8) {
+        let address = self.address(x, y);
+        self.occupation[address] = value;
+    }
+
+    /// Would a block at (x, y) hit a wall, the floor or the heap?
+    pub fn is_blocked(&self, x: i32, y: i32) -> bool {
+        self.get(x, y) == HEAP
+    }
+
+    /// Empty every cell
+    pub fn clear(&mut self) {
+        self.occupation.iter_mut().for_each(|cell| *cell = OPEN);
+        self.garbage.iter_mut().for_each(|row| *row = false);
+    }
+
+    /// Is there nothing on the heap at all? (eg after a perfect clear)
+    pub fn is_empty(&self) -> bool {
+        self.occupation.iter().all(|&cell| cell != HEAP)
+    }
+
+    /// Are all the cells in row y on the heap?
+    pub fn is_row_full(&self, y: i32) -> bool {
+        (0..self.width).all(|x| self.get(x, y) == HEAP)
+    }
+
+    /// Did row y come in as garbage? It still counts as garbage once blocks have filled its hole
+    pub fn is_garbage(&self, y: i32) -> bool {
+        y >= 0 && y < self.height && self.garbage[y as usize]
+    }
+
+    /// How many garbage rows are left on the board
+    pub fn garbage_rows(&self) -> usize {
+        self.garbage.iter().filter(|&&row| row).count()
+    }
+
+    /// Push a row of garbage in at the bottom, full apart from a hole at x, moving everything else up a row.
+    /// Whatever was in the top row is lost, so there shouldn't be a current tetromino when this happens
+    pub fn raise(&mut self, hole: i32) {
+        for y in 0..self.height - 1 {
+            for x in 0..self.width {
+                let value = self.get(x, y + 1);
+                self.set(x, y, value);
+            }
+        }
+        for x in 0..self.width {
+            self.set(x, self.height - 1, if x == hole { OPEN } else { HEAP });
+        }
+        self.garbage.remove(0);
+        self.garbage.push(true);
+    }
+
+    /// Remove any full rows, moving the heap above them down.
+    ///
+    /// Rows are checked from the bottom up. The returned rows are in the order they were removed,
+    /// each one relative to the board as it was *after* the previous removals, so replaying them
+    /// one at a time (remove row y, move everything above it down one) reproduces the result.
+    pub fn clear_full_rows(&mut self) -> Vec<i32> {
+        let mut cleared = Vec::new();
+        let mut y = self.height - 1;
+        while y >= 0 {
+            if self.is_row_full(y) {
+                cleared.push(y);
+                self.remove_row(y);
+                // We DON'T move up a row here because the row we just moved down might be full too
+            } else {
+                y -= 1;
+            }
+        }
+        cleared
+    }
+
+    /// Remove row y, full or not, moving everything above it down one
+    pub fn remove_row(&mut self, y: i32) {
+        self.garbage.remove(y as usize);
+        self.garbage.insert(0, false);
+
+        // Move everything above me down one, only go to row 1, which becomes what's in row 0
+        // - only move heap blocks
+        for y2 in (1..=y).rev() {
+            for x in 0..self.width {
+                let value = self.get(x, y2 - 1);
+                self.set(x, y2, value);
+            }
+        }
+        // - and make sure the first row is empty (of heap blocks - should never happen)
+        for x in 0..self.width {
+            if self.get(x, 0) == HEAP {
+                self.set(x, 0, OPEN);
+            }
+        }
+    }
+
+    /// Index into the occupation array
+    fn address(&self, x: i32, y: i32) -> usize {
+        (self.width * y + x) as usize
+    }
+}
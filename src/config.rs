@@ -0,0 +1,338 @@
+//! Settings that can be changed without recompiling: the size of the playing grid, the look of the window and
+//! the feel of the controls.
+//!
+//! They are read from a TOML file (`tetris.toml` unless `--config` says otherwise). Anything the file doesn't
+//! mention keeps its default, so the file only needs the values you want to change:
+//! ```toml
+//! field_height = 24
+//! block_size = 20.0
+//! border_color = [1.0, 0.5, 0.0, 0.6]
+//! das_ms = 133
+//! ```
+//! Colours are `[red, green, blue, alpha]`, each from 0.0 to 1.0. Sizes and positions are in pixels unless the
+//! name says otherwise. The defaults are what the game has always used, and a few of them differ between debug
+//! and release builds (the grid lines, the gaps between blocks and a slower drop to make debugging easier).
+
+use crate::game::Settings;
+use crate::mode::{Mode, TopOut};
+use crate::randomizer::RandomizerKind;
+use crate::scores::HighScores;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+/// A colour, [red, green, blue, alpha] from 0.0 to 1.0
+pub type Rgba = [f32; 4];
+
+/// Everything that can be set in the configuration file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameConfig {
+    // The playing grid
+    /// Width of the playing grid in blocks
+    pub field_width: i32,
+    /// Height of the playing grid in blocks
+    pub field_height: i32,
+    /// Where the tetrominos start relative to the field and tetronimo size, [x, y]. The y is also the number of rows
+    /// above the field for new tetrominoes. If it isn't given, they start in the middle with 4 rows above the field
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_pos: Option<(i32, i32)>,
+    /// Maximum level we allow
+    pub max_level: usize,
+    /// How the next tetromino is chosen: random, bag7, bag14 or tgm
+    pub randomizer: String,
+    /// How many upcoming tetrominoes to show in the next panel
+    pub next_count: usize,
+    /// How many games each high score table keeps
+    pub high_scores: usize,
+    /// How many lines a Sprint has to clear
+    pub sprint_lines: usize,
+    /// How many seconds an Ultra lasts
+    pub ultra_seconds: u64,
+    /// Does the level go up in an Ultra?
+    pub ultra_levels: bool,
+    /// How many rows of garbage a Dig has to clear
+    pub dig_lines: usize,
+    /// How many rows of garbage are on the field at a time in a Dig
+    pub dig_rows: usize,
+    /// What reaching the top does in a Zen: clear (the top rows of the heap) or reset (the whole heap)
+    pub zen_top_out: String,
+    /// How many rows from the top of the heap a Zen clears
+    pub zen_clear_rows: usize,
+
+    // The feel of the game
+    /// Slow down the automatic drop by this factor
+    pub drop_speed_factor: f32,
+    /// Delayed Auto Shift: milliseconds that Left/Right must be held before the tetromino keeps moving
+    pub das_ms: u64,
+    /// Auto Repeat Rate: milliseconds between moves once Left/Right is repeating (0 goes straight to the wall)
+    pub arr_ms: u64,
+    /// Holding Down drops this many times faster than the automatic drop
+    pub soft_drop_factor: u32,
+    /// Seconds a tetromino can stay on the ground before it locks
+    pub lock_delay: f32,
+    /// How many times moving or rotating on the ground can restart the lock delay
+    pub lock_resets: usize,
+
+    // The look of the window
+    /// Size of each block in pixels
+    pub block_size: f32,
+    /// The space between blocks in pixels
+    pub block_space: f32,
+    /// The border size in pixels
+    pub border_size: f32,
+    /// Should we display the grid lines?
+    pub draw_grid: bool,
+    /// Should we show the ghost (where the current tetromino will land) when the game starts? G toggles it
+    pub show_ghost: bool,
+    /// The ghost blocks are the tetromino's colour with this alpha
+    pub ghost_alpha: f32,
+    /// The current tetromino fades to this alpha as the lock delay runs out
+    pub lock_alpha: f32,
+    /// Seconds to show the kind of clear (eg 'T-Spin Double') under the score, and any banner
+    pub announce_time: f32,
+    /// The overall background, mostly overwritten by later elements
+    pub board_color: Rgba,
+    /// The main grid background
+    pub field_color: Rgba,
+    /// The grid lines - if they are displayed at all
+    pub grid_color: Rgba,
+    /// The borders around the field colour
+    pub border_color: Rgba,
+    /// The blocks of garbage rows (a Dig)
+    pub garbage_color: Rgba,
+    /// The score background
+    pub scorefield_color: Rgba,
+    /// The score label 'Score:'
+    pub scorelabel_color: Rgba,
+    /// The score text
+    pub score_color: Rgba,
+    /// The size of the score block
+    pub score_size: (f32, f32),
+    /// relative position of the score block from middle.right of field (in blocks)
+    pub score_space: (f32, f32),
+    /// The size of the hold panel (in blocks)
+    pub hold_size: (f32, f32),
+    /// relative position of the hold panel from top.left of field (in blocks)
+    pub hold_space: (f32, f32),
+    /// The space between the hold panel and the next panel below it (in blocks)
+    pub next_space: f32,
+    /// The height of each tetromino's slot in the next panel (in blocks)
+    pub next_slot: f32,
+    /// The tetrominoes in the next panel are drawn smaller than the real ones
+    pub next_scale: f32,
+    /// Size of the status label in pixels
+    pub statuslabel_size: f32,
+    /// The status label (Paused / game over etc)
+    pub statuslabel_color: Rgba,
+    /// Size of the banner over the playing field (eg 'Perfect Clear') in pixels
+    pub banner_size: f32,
+}
+
+impl Default for GameConfig {
+    fn default() -> GameConfig {
+        let debug = cfg!(debug_assertions);
+        GameConfig {
+            field_width: 10,
+            field_height: 20,
+            start_pos: None,
+            max_level: 20,
+            randomizer: RandomizerKind::Bag7.name().to_string(),
+            next_count: 5,
+            high_scores: HighScores::SIZE,
+            sprint_lines: 40,
+            ultra_seconds: 120,
+            ultra_levels: false,
+            dig_lines: 18,
+            dig_rows: 9,
+            zen_top_out: TopOut::ClearTop.name().to_string(),
+            zen_clear_rows: 8,
+
+            drop_speed_factor: if debug { 2.0 } else { 1.0 },
+            das_ms: 167,
+            arr_ms: 33,
+            soft_drop_factor: 20,
+            lock_delay: 0.5,
+            lock_resets: 15,
+
+            block_size: 16.0,
+            block_space: if debug { 1.0 } else { 0.0 },
+            border_size: 6.0,
+            draw_grid: debug,
+            show_ghost: true,
+            ghost_alpha: 0.25,
+            lock_alpha: 0.4,
+            announce_time: 2.0,
+            board_color: [0.1, 0.0, 0.2, 0.5],
+            field_color: [0.2, 0.2, 0.2, 0.5],
+            grid_color: [0.2, 0.5, 0.2, 0.5],
+            border_color: [1.0, 1.0, 1.0, 0.4],
+            garbage_color: [0.5, 0.5, 0.5, 1.0],
+            scorefield_color: [0.1, 0.0, 0.0, if debug { 0.5 } else { 0.0 }], // Note 0.0 alpha = clear
+            scorelabel_color: [1.0, 1.0, 1.0, 1.0],
+            score_color: [1.0, 0.50, 1.0, 1.0],
+            score_size: (100.0, 25.0),
+            score_space: (2.0, -5.0),
+            hold_size: (5.0, 4.0),
+            hold_space: (2.0, 1.5),
+            next_space: 2.5,
+            next_slot: 1.5,
+            next_scale: 0.5,
+            statuslabel_size: 50.0,
+            statuslabel_color: [1.0, 0.5, 0.5, 0.5],
+            banner_size: 40.0,
+        }
+    }
+}
+
+impl GameConfig {
+    /// The longest time any setting in seconds can be
+    pub const MAX_SECONDS: f32 = 60.0;
+
+    /// Read the configuration from a TOML file, and check it
+    pub fn load(path: &Path) -> Result<GameConfig, ConfigError> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    /// The configuration as TOML, eg to start a file from
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("every field can be written as TOML")
+    }
+
+    /// Check every value is one the game can use. Only the first problem is reported
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let check = |ok: bool, name: &str, expected: &str| {
+            if ok {
+                Ok(())
+            } else {
+                Err(ConfigError::Invalid(name.to_string(), expected.to_string()))
+            }
+        };
+        let fraction = |value: f32| (0.0..=1.0).contains(&value);
+        // Times end up in a Duration or a Timer, which can't hold infinity (or years)
+        let seconds = |value: f32| value.is_finite() && (0.0..=Self::MAX_SECONDS).contains(&value);
+
+        check(
+            RandomizerKind::from_name(&self.randomizer).is_some(),
+            "randomizer",
+            "one of random, bag7, bag14 or tgm",
+        )?;
+        check(self.high_scores >= 1, "high_scores", "at least 1")?;
+        check(
+            matches!(TopOut::from_name(&self.zen_top_out), Some(TopOut::ClearTop | TopOut::Reset)),
+            "zen_top_out",
+            "clear or reset",
+        )?;
+        check(
+            self.drop_speed_factor > 0.0 && seconds(self.drop_speed_factor),
+            "drop_speed_factor",
+            "more than 0, and no more than 60",
+        )?;
+        check(self.soft_drop_factor >= 1, "soft_drop_factor", "at least 1")?;
+        check(seconds(self.lock_delay), "lock_delay", "from 0 to 60 seconds")?;
+        check(self.block_size > 0.0, "block_size", "more than 0")?;
+        check(self.block_space >= 0.0, "block_space", "0 or more")?;
+        check(self.border_size >= 0.0, "border_size", "0 or more")?;
+        check(fraction(self.ghost_alpha), "ghost_alpha", "from 0.0 to 1.0")?;
+        check(fraction(self.lock_alpha), "lock_alpha", "from 0.0 to 1.0")?;
+        check(seconds(self.announce_time), "announce_time", "from 0 to 60 seconds")?;
+        check(self.next_scale > 0.0, "next_scale", "more than 0")?;
+        check(self.statuslabel_size > 0.0, "statuslabel_size", "more than 0")?;
+        check(self.banner_size > 0.0, "banner_size", "more than 0")?;
+        for (name, color) in [
+            ("board_color", self.board_color),
+            ("field_color", self.field_color),
+            ("grid_color", self.grid_color),
+            ("border_color", self.border_color),
+            ("garbage_color", self.garbage_color),
+            ("scorefield_color", self.scorefield_color),
+            ("scorelabel_color", self.scorelabel_color),
+            ("score_color", self.score_color),
+            ("statuslabel_color", self.statuslabel_color),
+        ] {
+            check(color.iter().all(|&value| fraction(value)), name, "[red, green, blue, alpha] each from 0.0 to 1.0")?;
+        }
+
+        // The rules of the game are checked the same way as a replay's, with the names they have here.
+        // Everything settings() needs has been checked above
+        self.settings().validate().map_err(|invalid| {
+            let name = match invalid.name {
+                "width" => "field_width",
+                "height" => "field_height",
+                "ultra_time" => "ultra_seconds",
+                name => name,
+            };
+            ConfigError::Invalid(name.to_string(), invalid.expected.to_string())
+        })
+    }
+
+    /// Where tetrominoes start, as given or worked out from the width of the field
+    pub fn start_pos(&self) -> (i32, i32) {
+        self.start_pos.unwrap_or_else(|| Settings::start_for(self.field_width))
+    }
+
+    /// The rules for a game with this configuration, with no fixed seed. The game is a Marathon until another mode is chosen
+    pub fn settings(&self) -> Settings {
+        Settings {
+            width: self.field_width,
+            height: self.field_height,
+            start_pos: self.start_pos(),
+            max_level: self.max_level,
+            preview: self.next_count,
+            randomizer: RandomizerKind::from_name(&self.randomizer).unwrap_or(RandomizerKind::Bag7),
+            seed: None,
+            lock_delay: Duration::from_secs_f32(self.lock_delay),
+            lock_resets: self.lock_resets,
+            mode: Mode::Marathon,
+            sprint_lines: self.sprint_lines,
+            ultra_time: Duration::from_secs(self.ultra_seconds),
+            ultra_levels: self.ultra_levels,
+            dig_lines: self.dig_lines,
+            dig_rows: self.dig_rows,
+            zen_top_out: TopOut::from_name(&self.zen_top_out).unwrap_or(TopOut::ClearTop),
+            zen_clear_rows: self.zen_clear_rows,
+        }
+    }
+}
+
+impl std::str::FromStr for GameConfig {
+    type Err = ConfigError;
+
+    fn from_str(text: &str) -> Result<GameConfig, ConfigError> {
+        let config: GameConfig = toml::from_str(text).map_err(|error| ConfigError::Parse(error.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+/// Why a configuration couldn't be used
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The file couldn't be read at all
+    Io(io::Error),
+    /// The file isn't TOML, or has a value of the wrong type or a name we don't know (the message says where)
+    Parse(String),
+    /// A value is out of range: the name, and what it should be
+    Invalid(String, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(error) => write!(f, "{}", error),
+            ConfigError::Parse(message) => write!(f, "{}", message),
+            ConfigError::Invalid(name, expected) => write!(f, "{} should be {}", name, expected),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(error: io::Error) -> ConfigError {
+        ConfigError::Io(error)
+    }
+}
@@ -0,0 +1,1124 @@
+use crate::board::{Board, CURRENT, HEAP, OPEN};
+use crate::master;
+use crate::mode::{Mode, TopOut};
+use crate::randomizer::{Randomizer, RandomizerKind};
+use crate::srs::{self, Rotation};
+use crate::tetromino::TetrominoType;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cmp::min;
+use std::collections::VecDeque;
+use std::mem;
+use std::time::Duration;
+
+/// The fixed parameters of a game
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /// Width of the playing grid in blocks
+    pub width: i32,
+
+    /// Height of the playing grid in blocks (not including the buffer rows)
+    pub height: i32,
+
+    /// Where the tetrominos start relative to the field and tetronimo size.
+    /// The y value is also the number of buffer rows above the field.
+    pub start_pos: (i32, i32),
+
+    /// Maximum level we allow
+    pub max_level: usize,
+
+    /// How many upcoming tetrominoes are known (and can be shown) in advance
+    pub preview: usize,
+
+    /// How the upcoming tetrominoes are chosen
+    pub randomizer: RandomizerKind,
+
+    /// Use this seed for every game, rather than a new random one each time
+    pub seed: Option<u64>,
+
+    /// How long a tetromino can stay on the ground before it locks (zero locks as soon as it lands)
+    pub lock_delay: Duration,
+
+    /// How many times moving or rotating on the ground can restart the lock delay
+    pub lock_resets: usize,
+
+    /// The kind of game, and what ends it
+    pub mode: Mode,
+
+    /// How many lines a Sprint has to clear
+    pub sprint_lines: usize,
+
+    /// How long an Ultra lasts
+    pub ultra_time: Duration,
+
+    /// Does clearing lines raise the level in an Ultra, as it does in the other modes?
+    pub ultra_levels: bool,
+
+    /// How many rows of garbage a Dig has to clear
+    pub dig_lines: usize,
+
+    /// How many of them are on the field at a time, more come in from the bottom as they are cleared
+    pub dig_rows: usize,
+
+    /// What reaching the top does in a Zen, rather than ending the game
+    pub zen_top_out: TopOut,
+
+    /// How many rows from the top of the heap `TopOut::ClearTop` takes away
+    pub zen_clear_rows: usize,
+}
+
+impl Settings {
+    /// How many buffer rows there are above the field for new tetrominoes, unless `start_pos` says otherwise
+    pub const BUFFER_ROWS: i32 = 4;
+
+    /// Split times are taken every this many lines
+    pub const SPLIT_LINES: usize = 10;
+
+    /// The default settings for a playing grid of this size
+    pub fn sized(width: i32, height: i32) -> Settings {
+        Settings {
+            width,
+            height,
+            start_pos: Settings::start_for(width),
+            ..Settings::default()
+        }
+    }
+
+    /// Where tetrominoes start on a grid this wide: just left of the middle (column 4 of 10),
+    /// but never so far right that an I (4 wide) doesn't fit
+    pub fn start_for(width: i32) -> (i32, i32) {
+        ((width / 2 - 1).min(width - 4).max(0), Settings::BUFFER_ROWS)
+    }
+
+    /// The widest or highest playing grid we allow
+    pub const MAX_SIZE: i32 = 1000;
+
+    /// The longest lock delay we allow
+    pub const MAX_LOCK_DELAY: Duration = Duration::from_secs(60);
+
+    /// Check the settings are ones a game can be played with, wherever they came from (the configuration or a
+    /// replay). Only the first problem is reported
+    pub fn validate(&self) -> Result<(), InvalidSetting> {
+        let check = |ok: bool, name: &'static str, expected: &'static str| {
+            if ok {
+                Ok(())
+            } else {
+                Err(InvalidSetting { name, expected })
+            }
+        };
+        let size = 4..=Settings::MAX_SIZE;
+
+        check(size.contains(&self.width), "width", "from 4 (the width of an I) to 1000")?;
+        check(size.contains(&self.height), "height", "from 4 (the height of an I) to 1000")?;
+        let (x, y) = self.start_pos;
+        check(
+            x >= 0 && x + 4 <= self.width,
+            "start_pos",
+            "an x that leaves room for a tetromino (4 wide) across the field",
+        )?;
+        check(
+            (2..=Settings::MAX_SIZE).contains(&y),
+            "start_pos",
+            "a y of at least 2 (rows above the field for new tetrominoes, which are up to 2 high)",
+        )?;
+        check(self.max_level >= 1, "max_level", "at least 1")?;
+        check(self.lock_delay <= Settings::MAX_LOCK_DELAY, "lock_delay", "from 0 to 60 seconds")?;
+        check(self.sprint_lines >= 1, "sprint_lines", "at least 1")?;
+        check(self.ultra_time >= Duration::from_secs(1), "ultra_time", "at least 1 second")?;
+        check(self.dig_lines >= 1, "dig_lines", "at least 1")?;
+        check(
+            self.dig_rows >= 1 && (self.dig_rows as i32) < self.height,
+            "dig_rows",
+            "at least 1, and less than the height of the field",
+        )?;
+        check(
+            matches!(self.zen_top_out, TopOut::ClearTop | TopOut::Reset),
+            "zen_top_out",
+            "clear or reset",
+        )?;
+        check(self.zen_clear_rows >= 1, "zen_clear_rows", "at least 1")?;
+        Ok(())
+    }
+}
+
+/// A setting a game can't be played with (see `Settings::validate`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidSetting {
+    /// The name of the setting, as in `Settings`
+    pub name: &'static str,
+    /// What it should be
+    pub expected: &'static str,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            width: 10,
+            height: 20,
+            start_pos: Settings::start_for(10),
+            max_level: 20,
+            preview: 5,
+            randomizer: RandomizerKind::Bag7,
+            seed: None,
+            lock_delay: Duration::from_millis(500),
+            lock_resets: 15,
+            mode: Mode::Marathon,
+            sprint_lines: 40,
+            ultra_time: Duration::from_secs(120),
+            ultra_levels: false,
+            dig_lines: 18,
+            dig_rows: 9,
+            zen_top_out: TopOut::ClearTop,
+            zen_clear_rows: 8,
+        }
+    }
+}
+
+/// Seconds per row of the automatic drop at a level. 'Guideline' rule: Time = (0.8-((Level-1)*0.007))^(Level-1)
+pub fn drop_speed(level: usize) -> f32 {
+    (0.8 - ((level - 1) as f32 * 0.007)).powf((level - 1) as f32)
+}
+
+/// The things a player can ask the current tetromino to do
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    SoftDrop,
+    RotateClockwise,
+    RotateAntiClockwise,
+    HardDrop,
+    Hold,
+}
+
+impl Action {
+    /// Every action
+    pub const ALL: [Action; 7] = [
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::SoftDrop,
+        Action::RotateClockwise,
+        Action::RotateAntiClockwise,
+        Action::HardDrop,
+        Action::Hold,
+    ];
+
+    /// The name used for this action in files
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::MoveLeft => "MoveLeft",
+            Action::MoveRight => "MoveRight",
+            Action::SoftDrop => "SoftDrop",
+            Action::RotateClockwise => "RotateClockwise",
+            Action::RotateAntiClockwise => "RotateAntiClockwise",
+            Action::HardDrop => "HardDrop",
+            Action::Hold => "Hold",
+        }
+    }
+
+    /// The action with this name, if there is one
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.iter().copied().find(|action| action.name() == name)
+    }
+}
+
+/// Something that happened in the game that a view might want to show
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// A new current tetromino has appeared with these blocks
+    Spawned(TetrominoType, [(i32, i32); 4]),
+    /// The current tetromino has moved or rotated, these are its new blocks
+    Moved([(i32, i32); 4]),
+    /// The current tetromino has landed and its blocks (which are here) are now part of the heap
+    Locked([(i32, i32); 4]),
+    /// Full rows have been removed, see `Board::clear_full_rows` for how to replay them
+    LinesCleared(Vec<i32>),
+    /// The current tetromino (of this type) has been put in the hold slot, the next Spawned replaces it
+    Held(TetrominoType),
+    /// Rows of garbage have come in at the bottom, each one pushing the heap up a row. The hole in each row,
+    /// in the order they came in
+    Garbage(Vec<i32>),
+    /// A tetromino locked (or garbage pushed the heap) in the buffer rows above the field
+    GameOver,
+    /// The same, but the game goes on (a Zen). The rows taken away from the heap follow, as LinesCleared
+    ToppedOut,
+    /// The goal of the mode has been reached (eg all the lines of a Sprint, or the time of an Ultra has run out),
+    /// which ends the game too
+    Finished,
+    /// A locked tetromino scored for clearing lines or a spin (after any LinesCleared)
+    Scored(Clear),
+}
+
+/// A T-spin: the last thing a T did before it locked was rotate, into a spot with three of its four corners filled
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TSpin {
+    /// Only one of the corners the T points at is filled
+    Mini,
+    /// Both the corners the T points at are filled, or it took the last kick to get there
+    Full,
+}
+
+/// What a locked tetromino did that scores
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Clear {
+    pub lines: usize,
+    pub t_spin: Option<TSpin>,
+    pub combo: usize, // how many clears in a row came before this one, 0 if it is the first
+    pub back_to_back: bool, // a difficult clear (Tetris or T-spin) straight after another one
+    pub perfect: bool, // nothing left on the heap afterwards
+    pub points: usize,
+}
+
+impl Clear {
+    /// Is it a 'difficult' clear, one that keeps a back-to-back going? Tetrises and T-spins that clear lines
+    pub fn is_difficult(&self) -> bool {
+        self.lines >= 4 || (self.lines > 0 && self.t_spin.is_some())
+    }
+
+    /// What to call it, eg "Tetris" or "T-Spin Mini Single", or "Back-to-Back Tetris" when it carries on a streak
+    pub fn name(&self) -> String {
+        let lines = match self.lines {
+            0 => "",
+            1 => "Single",
+            2 => "Double",
+            3 => "Triple",
+            _ => "Tetris",
+        };
+        let spin = match self.t_spin {
+            Some(TSpin::Full) => "T-Spin",
+            Some(TSpin::Mini) => "T-Spin Mini",
+            None => "",
+        };
+        let streak = if self.back_to_back { "Back-to-Back" } else { "" };
+        format!("{} {} {}", streak, spin, lines).split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+}
+
+/// Counts of what has happened in a game, from the first tetromino
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub pieces: usize, // tetrominoes locked
+    pub lines: usize,
+    pub tetrises: usize,
+    pub t_spins: usize, // including Minis, with or without lines
+    pub max_combo: usize,
+    pub perfect_clears: usize,
+    pub garbage_lines: usize, // garbage rows cleared (a Dig)
+    pub top_outs: usize, // times the heap reached the top and the game went on (a Zen)
+}
+
+/// The current tetromino
+#[derive(Debug, Clone)]
+pub struct Piece {
+    pub tetromino_type: TetrominoType,
+    pub rotation: Rotation,
+    pub x: i32, // position of the bounding box in the playing grid
+    pub y: i32,
+    pub blocks: [(i32, i32); 4], // position of each block in the playing grid
+}
+
+impl Piece {
+    /// Where the blocks of a tetromino would be with its bounding box at (x, y)
+    pub fn blocks_at(tetromino_type: TetrominoType, rotation: Rotation, x: i32, y: i32) -> [(i32, i32); 4] {
+        let mut blocks = tetromino_type.blocks_in(rotation);
+        for block in blocks.iter_mut() {
+            block.0 += x;
+            block.1 += y;
+        }
+        blocks
+    }
+}
+
+/// The complete state of a game.
+///
+/// Input goes in through `apply`, `gravity` and `step`, and what happened comes out of `take_events`.
+#[derive(Debug)]
+pub struct Game {
+    settings: Settings,
+    board: Board,
+    piece: Option<Piece>,
+    score: usize,
+    level: usize,
+    lines_cleared: usize,
+    drop_speed: f32,
+    game_over: bool,
+    held: Option<TetrominoType>,
+    hold_used: bool, // only one hold per tetromino, until it locks
+    lock_timer: Option<Duration>, // how long the current tetromino has been on the ground, None while it can fall
+    lock_resets: usize, // how many times the lock delay has been restarted
+    lowest_y: i32, // the lowest the current tetromino has been, going lower gives it a fresh set of resets
+    last_kick: Option<usize>, // which kick the last rotation used, if rotating was the last thing the tetromino did
+    combo: Option<usize>, // clears in a row before the latest one, None once a tetromino locks without clearing
+    back_to_back: bool, // was the latest line clear a difficult one?
+    stats: Stats,
+    time: Duration, // game time since the first tetromino, from `step`
+    splits: Vec<Duration>, // the game time every Settings::SPLIT_LINES lines
+    finished: bool, // the game ended by reaching the mode's goal, rather than the top
+    garbage_left: usize, // rows of garbage still to come in (a Dig)
+    master_level: usize, // the level a Master counts, from 0 to 999 (see `master`)
+    entry_delay: Option<Duration>, // how long until the next tetromino appears (ARE, a Master), if one is on its way
+    fall: u64, // how far the current tetromino is on its way to the next row under its own gravity (see `master::fall`)
+    seed: u64,
+    rng: StdRng, // every random choice comes from here, so the same seed gives the same game
+    randomizer: Box<dyn Randomizer>,
+    next: VecDeque<TetrominoType>,
+    events: Vec<Event>,
+}
+
+impl Game {
+    /// An empty game, waiting for `reset` (or `spawn`) to provide the first tetromino
+    pub fn new(settings: Settings) -> Game {
+        let board = Board::new(settings.width, settings.height + settings.start_pos.1);
+        let randomizer = settings.randomizer.create();
+        let seed = settings.seed.unwrap_or_else(rand::random);
+        let mut game = Game {
+            settings,
+            board,
+            piece: None,
+            score: 0,
+            level: 1,
+            lines_cleared: 0,
+            drop_speed: 1.0,
+            game_over: false,
+            held: None,
+            hold_used: false,
+            lock_timer: None,
+            lock_resets: 0,
+            lowest_y: 0,
+            last_kick: None,
+            combo: None,
+            back_to_back: false,
+            stats: Stats::default(),
+            time: Duration::ZERO,
+            splits: Vec::new(),
+            finished: false,
+            garbage_left: 0,
+            master_level: 0,
+            entry_delay: None,
+            fall: 0,
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            randomizer,
+            next: VecDeque::new(),
+            events: Vec::new(),
+        };
+        game.fill_queue();
+        game
+    }
+
+    /// Start a new game with a random tetromino, using the seed from the settings if there is one
+    pub fn reset(&mut self) {
+        let seed = self.settings.seed.unwrap_or_else(rand::random);
+        self.reset_with_seed(seed);
+    }
+
+    /// Start a new game with a random tetromino, chosen using this seed
+    pub fn reset_with_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = StdRng::seed_from_u64(seed);
+        self.board.clear();
+        self.piece = None;
+        self.score = 0;
+        self.level = 1;
+        self.lines_cleared = 0;
+        self.drop_speed = 1.0;
+        self.game_over = false;
+        self.held = None;
+        self.hold_used = false;
+        self.combo = None;
+        self.back_to_back = false;
+        self.stats = Stats::default();
+        self.time = Duration::ZERO;
+        self.splits.clear();
+        self.finished = false;
+        self.randomizer = self.settings.randomizer.create();
+        self.next.clear();
+        self.fill_queue();
+        self.events.clear();
+        self.master_level = 0;
+        self.entry_delay = None;
+        self.garbage_left = match self.settings.mode {
+            Mode::Dig => self.settings.dig_lines,
+            _ => 0,
+        };
+        self.feed_garbage();
+        let first = self.next_type();
+        self.spawn(first);
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    /// The current tetromino, if there is one
+    pub fn piece(&self) -> Option<&Piece> {
+        self.piece.as_ref()
+    }
+
+    pub fn score(&self) -> usize {
+        self.score
+    }
+
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    /// Lines cleared since the last level change
+    pub fn lines_cleared(&self) -> usize {
+        self.lines_cleared
+    }
+
+    /// Seconds per row of automatic drop at the current level
+    pub fn drop_speed(&self) -> f32 {
+        self.drop_speed
+    }
+
+    pub fn is_game_over(&self) -> bool {
+        self.game_over
+    }
+
+    /// Did the game end by reaching the goal of its mode (eg all the lines of a Sprint)?
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// The tetromino in the hold slot, if any
+    pub fn held(&self) -> Option<TetrominoType> {
+        self.held
+    }
+
+    /// The seed for this game's random choices
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The upcoming tetrominoes, the next one first
+    pub fn next_queue(&self) -> &VecDeque<TetrominoType> {
+        &self.next
+    }
+
+    /// Can the current tetromino be held? Only once until it locks
+    pub fn can_hold(&self) -> bool {
+        !self.hold_used
+    }
+
+    /// How many line clears in a row there have been after the first, None if the last tetromino didn't clear any
+    pub fn combo(&self) -> Option<usize> {
+        self.combo
+    }
+
+    /// Is a back-to-back streak going? The next difficult clear (Tetris or T-spin) scores half as much again
+    pub fn back_to_back(&self) -> bool {
+        self.back_to_back
+    }
+
+    /// What has happened in this game so far
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// How long the game has been going, in game time (the frames given to `step`), until it ended
+    pub fn time(&self) -> Duration {
+        self.time
+    }
+
+    /// The game time when each `Settings::SPLIT_LINES` lines had been cleared, the first split first
+    pub fn splits(&self) -> &[Duration] {
+        &self.splits
+    }
+
+    /// How long the game can go on for, if its mode has a time limit (an Ultra)
+    pub fn time_limit(&self) -> Option<Duration> {
+        match self.settings.mode {
+            Mode::Ultra => Some(self.settings.ultra_time),
+            _ => None,
+        }
+    }
+
+    /// The level a Master counts, from 0 to 999. None in the other modes, which only have `level`
+    pub fn master_level(&self) -> Option<usize> {
+        match self.settings.mode {
+            Mode::Master => Some(self.master_level),
+            _ => None,
+        }
+    }
+
+    /// Does the current tetromino fall by itself (a Master), rather than waiting for `gravity`?
+    pub fn falls_by_itself(&self) -> bool {
+        self.settings.mode == Mode::Master
+    }
+
+    /// How far through the lock delay the current tetromino is, from 0.0 (just landed) to 1.0.
+    /// None if it isn't on the ground
+    pub fn lock_progress(&self) -> Option<f32> {
+        let on_ground = self.lock_timer?;
+        let lock_delay = self.lock_delay();
+        if lock_delay.is_zero() {
+            return Some(1.0);
+        }
+        Some((on_ground.as_secs_f32() / lock_delay.as_secs_f32()).min(1.0))
+    }
+
+    /// Where the current tetromino would land if it dropped straight down from here (the 'ghost')
+    ///
+    /// Works from the occupation of the board, so the current tetromino's own blocks don't get in the way
+    pub fn ghost(&self) -> Option<[(i32, i32); 4]> {
+        let piece = self.piece.as_ref()?;
+        let mut y = piece.y;
+        while !Piece::blocks_at(piece.tetromino_type, piece.rotation, piece.x, y + 1)
+            .iter()
+            .any(|&(x, y)| self.board.is_blocked(x, y))
+        {
+            y += 1;
+        }
+        Some(Piece::blocks_at(piece.tetromino_type, piece.rotation, piece.x, y))
+    }
+
+    /// Everything that has happened since the last call, oldest first
+    pub fn take_events(&mut self) -> Vec<Event> {
+        mem::take(&mut self.events)
+    }
+
+    /// Make a new current tetromino of the given type at the start position.
+    /// Any existing current tetromino is removed.
+    pub fn spawn(&mut self, tetromino_type: TetrominoType) {
+        if let Some(piece) = self.piece.take() {
+            for (x, y) in piece.blocks {
+                self.board.set(x, y, OPEN);
+            }
+        }
+
+        // TODO: random rotation, random horizontal position?
+        // The lowest blocks start in the bottom row of the top buffer
+        let lowest = tetromino_type.blocks().iter().map(|b| b.1).max().unwrap_or(0);
+        let x = self.settings.start_pos.0;
+        let y = self.settings.start_pos.1 - 1 - lowest;
+        let blocks = Piece::blocks_at(tetromino_type, Rotation::Zero, x, y);
+        for &(x, y) in blocks.iter() {
+            self.board.set(x, y, CURRENT);
+        }
+
+        self.lock_timer = None;
+        self.lock_resets = 0;
+        self.lowest_y = y;
+        self.last_kick = None;
+        self.fall = 0;
+        self.piece = Some(Piece {
+            tetromino_type,
+            rotation: Rotation::Zero,
+            x,
+            y,
+            blocks,
+        });
+        self.events.push(Event::Spawned(tetromino_type, blocks));
+
+        // At 20G a tetromino is on the ground as soon as it appears
+        if self.falls_by_itself() && master::gravity(self.master_level) >= master::TWENTY_G {
+            while self.move_down() {}
+        }
+    }
+
+    /// Ask the current tetromino to do something. Returns false if nothing happened.
+    pub fn apply(&mut self, action: Action) -> bool {
+        if self.game_over || self.piece.is_none() {
+            return false;
+        }
+
+        let done = match action {
+            Action::MoveLeft => self.shift(-1, 0),
+            Action::MoveRight => self.shift(1, 0),
+            Action::SoftDrop => self.soft_drop(),
+            Action::RotateClockwise => self.rotate(true),
+            Action::RotateAntiClockwise => self.rotate(false),
+            Action::HardDrop => self.hard_drop(),
+            Action::Hold => self.hold(),
+        };
+
+        // Sliding and spinning on the ground buys more time, but only so many times
+        let sideways_or_turned = !matches!(action, Action::SoftDrop | Action::HardDrop | Action::Hold);
+        if done && sideways_or_turned && self.lock_timer.is_some() && self.lock_resets < self.settings.lock_resets {
+            self.lock_timer = Some(Duration::ZERO);
+            self.lock_resets += 1;
+        }
+        done
+    }
+
+    /// The automatic drop: move the current tetromino down one row. Returns false if it is on the ground
+    pub fn gravity(&mut self) -> bool {
+        if self.game_over || self.piece.is_none() {
+            return false;
+        }
+        self.move_down()
+    }
+
+    /// Advance one frame, this much time after the last one.
+    /// Nothing moves by itself between inputs (that's `gravity`) except in a Master, but a tetromino on the
+    /// ground locks when it has been there for the lock delay
+    pub fn step(&mut self, elapsed: Duration) {
+        if self.game_over || (self.piece.is_none() && self.entry_delay.is_none()) {
+            return;
+        }
+        self.time += elapsed;
+
+        // Time's up stops the game there and then, whatever the current tetromino is doing. It doesn't lock
+        if let Some(limit) = self.time_limit() {
+            if self.time >= limit {
+                self.time = limit;
+                self.finish();
+                return;
+            }
+        }
+
+        // Waiting for the next tetromino to appear (ARE)
+        if let Some(delay) = self.entry_delay {
+            if delay > elapsed {
+                self.entry_delay = Some(delay - elapsed);
+            } else {
+                self.entry_delay = None;
+                self.spawn_next();
+            }
+            return;
+        }
+
+        // A Master's own gravity: however many rows this frame is worth, with any part of a row kept for the next
+        if self.falls_by_itself() {
+            self.fall += master::fall(master::gravity(self.master_level), elapsed);
+            while self.fall >= master::ROW {
+                self.fall -= master::ROW;
+                if !self.move_down() {
+                    self.fall = 0;
+                    break;
+                }
+            }
+        }
+
+        // Moving off a ledge stops the clock, but doesn't give back any resets
+        if !self.is_grounded() {
+            self.lock_timer = None;
+            return;
+        }
+        let on_ground = self.lock_timer.unwrap_or_default() + elapsed;
+        if on_ground >= self.lock_delay() {
+            self.lock();
+        } else {
+            self.lock_timer = Some(on_ground);
+        }
+    }
+
+    /// The player moves down a row, for 1 point a row (guideline). Returns false if it is on the ground
+    fn soft_drop(&mut self) -> bool {
+        let moved = self.move_down();
+        if moved {
+            self.score += 1;
+        }
+        moved
+    }
+
+    /// Drop straight to the landing position and lock there, for 2 points a row (guideline)
+    fn hard_drop(&mut self) -> bool {
+        let rows = match (&self.piece, self.ghost()) {
+            (Some(piece), Some(ghost)) => ghost[0].1 - piece.blocks[0].1,
+            _ => return false,
+        };
+        if rows > 0 {
+            self.shift(0, rows);
+            self.score += 2 * rows as usize;
+        }
+        self.lock();
+        true
+    }
+
+    /// Move down a row if we can. If we can't we must have landed on something, and `step` will lock us
+    /// after the lock delay - or now, if there isn't one
+    fn move_down(&mut self) -> bool {
+        if !self.shift(0, 1) {
+            if self.lock_delay().is_zero() {
+                self.lock();
+            } else if self.lock_timer.is_none() {
+                self.lock_timer = Some(Duration::ZERO);
+            }
+            return false;
+        }
+
+        self.lock_timer = None;
+        let y = self.piece.as_ref().map_or(0, |piece| piece.y);
+        if y > self.lowest_y {
+            self.lowest_y = y;
+            self.lock_resets = 0;
+        }
+        true
+    }
+
+    /// Is there something directly under the current tetromino?
+    fn is_grounded(&self) -> bool {
+        match &self.piece {
+            Some(piece) => piece.blocks.iter().any(|&(x, y)| self.board.is_blocked(x, y + 1)),
+            None => false,
+        }
+    }
+
+    /// Move the current tetromino if there is room
+    fn shift(&mut self, dx: i32, dy: i32) -> bool {
+        let (rotation, x, y) = match &self.piece {
+            Some(piece) => (piece.rotation, piece.x, piece.y),
+            None => return false,
+        };
+        let moved = self.place(rotation, x + dx, y + dy);
+        if moved {
+            self.last_kick = None; // not a spin any more
+        }
+        moved
+    }
+
+    /// Rotate the current tetromino using SRS, trying each kick in turn until one fits
+    fn rotate(&mut self, clockwise: bool) -> bool {
+        let (tetromino_type, from, x, y) = match &self.piece {
+            Some(piece) => (piece.tetromino_type, piece.rotation, piece.x, piece.y),
+            None => return false,
+        };
+        let to = if clockwise { from.clockwise() } else { from.anti_clockwise() };
+
+        let kicks = srs::kicks(tetromino_type, from, to).unwrap_or_default();
+        // The kick tables have +y going up, we have +y going down
+        let kick = kicks
+            .iter()
+            .position(|&(kick_x, kick_y)| self.place(to, x + kick_x, y - kick_y));
+        if kick.is_some() {
+            self.last_kick = kick;
+        }
+        kick.is_some()
+    }
+
+    /// Was the current tetromino's last move a T-spin? Uses the 3-corner rule (guideline)
+    fn t_spin(&self, piece: &Piece) -> Option<TSpin> {
+        if piece.tetromino_type != TetrominoType::T {
+            return None;
+        }
+        let kick = self.last_kick?;
+
+        // The corners of the T's bounding box, clockwise from the top left. Walls and the floor count as filled
+        let (x, y) = (piece.x, piece.y);
+        let filled: Vec<bool> = [(x, y), (x + 2, y), (x + 2, y + 2), (x, y + 2)]
+            .iter()
+            .map(|&(x, y)| self.board.is_blocked(x, y))
+            .collect();
+        if filled.iter().filter(|&&filled| filled).count() < 3 {
+            return None;
+        }
+
+        // The two corners either side of the way the T points
+        let front = match piece.rotation {
+            Rotation::Zero => [0, 1],
+            Rotation::Right => [1, 2],
+            Rotation::Two => [2, 3],
+            Rotation::Left => [3, 0],
+        };
+        // The last kick (eg the TST twist) always counts as a full T-spin
+        if front.iter().all(|&corner| filled[corner]) || kick == 4 {
+            Some(TSpin::Full)
+        } else {
+            Some(TSpin::Mini)
+        }
+    }
+
+    /// Move the current tetromino to this position and rotation if none of its blocks collide with anything
+    fn place(&mut self, rotation: Rotation, x: i32, y: i32) -> bool {
+        let piece = match self.piece.as_mut() {
+            Some(piece) => piece,
+            None => return false,
+        };
+        let blocks = Piece::blocks_at(piece.tetromino_type, rotation, x, y);
+        if blocks.iter().any(|&(x, y)| self.board.is_blocked(x, y)) {
+            return false;
+        }
+
+        for &(x, y) in piece.blocks.iter() {
+            self.board.set(x, y, OPEN);
+        }
+        for &(x, y) in blocks.iter() {
+            self.board.set(x, y, CURRENT);
+        }
+        piece.rotation = rotation;
+        piece.x = x;
+        piece.y = y;
+        piece.blocks = blocks;
+        self.events.push(Event::Moved(blocks));
+        true
+    }
+
+    /// Swap the current tetromino with the one in the hold slot (or the next one if the slot is empty)
+    fn hold(&mut self) -> bool {
+        if self.hold_used {
+            return false;
+        }
+        let piece = match self.piece.take() {
+            Some(piece) => piece,
+            None => return false,
+        };
+
+        for &(x, y) in piece.blocks.iter() {
+            self.board.set(x, y, OPEN);
+        }
+        self.events.push(Event::Held(piece.tetromino_type));
+
+        // The held tetromino starts again from the top
+        let next = self.held.replace(piece.tetromino_type);
+        let tetromino_type = next.unwrap_or_else(|| self.next_type());
+        self.spawn(tetromino_type);
+        self.hold_used = true;
+        true
+    }
+
+    /// Move the current tetromino to the heap, then clear rows, score and spawn the next one
+    fn lock(&mut self) {
+        let piece = match self.piece.take() {
+            Some(piece) => piece,
+            None => return,
+        };
+
+        let t_spin = self.t_spin(&piece);
+        for &(x, y) in piece.blocks.iter() {
+            self.board.set(x, y, HEAP);
+        }
+        self.stats.pieces += 1;
+        self.events.push(Event::Locked(piece.blocks));
+
+        // If any block is still in the top buffer, we have reached the top
+        if piece.blocks.iter().any(|&(_x, y)| y < self.settings.start_pos.1) {
+            self.top_out();
+            if self.game_over {
+                return;
+            }
+        }
+
+        self.clear_rows(t_spin);
+        if self.goal_reached() {
+            self.finish();
+            return;
+        }
+
+        // Garbage coming in can push the heap up into the buffer rows too
+        self.feed_garbage();
+        let width = self.settings.width;
+        if (0..self.settings.start_pos.1).any(|y| (0..width).any(|x| self.board.get(x, y) == HEAP)) {
+            self.top_out();
+            if self.game_over {
+                return;
+            }
+        }
+
+        self.hold_used = false;
+
+        // A Master waits a moment (ARE) for the next tetromino, see `step`
+        if self.settings.mode == Mode::Master {
+            self.entry_delay = Some(master::entry_delay(self.master_level));
+            return;
+        }
+        self.spawn_next();
+    }
+
+    /// Bring in the next tetromino from the queue. In a Master each one goes up a level, as far as the section stop
+    fn spawn_next(&mut self) {
+        if self.settings.mode == Mode::Master && self.master_level < master::section_stop(self.master_level) {
+            self.master_level += 1;
+        }
+        let tetromino_type = self.next_type();
+        self.spawn(tetromino_type);
+    }
+
+    /// How long a tetromino can stay on the ground: from the settings, or from the level in a Master
+    fn lock_delay(&self) -> Duration {
+        match self.settings.mode {
+            Mode::Master => master::lock_delay(self.master_level),
+            _ => self.settings.lock_delay,
+        }
+    }
+
+    /// Has the game done what its mode asks?
+    fn goal_reached(&self) -> bool {
+        match self.settings.mode {
+            Mode::Marathon | Mode::Zen => false,
+            Mode::Sprint => self.stats.lines >= self.settings.sprint_lines,
+            Mode::Ultra => false, // the clock ends an Ultra, see `step`
+            Mode::Dig => self.stats.garbage_lines >= self.settings.dig_lines,
+            Mode::Master => self.master_level >= master::MAX_LEVEL,
+        }
+    }
+
+    /// Bring in garbage from the bottom, one row at a time with a random hole, until there are `dig_rows` of it
+    /// on the field or there is none left to come
+    fn feed_garbage(&mut self) {
+        let mut holes = Vec::new();
+        while self.garbage_left > 0 && self.board.garbage_rows() < self.settings.dig_rows {
+            let hole = self.rng.gen_range(0, self.settings.width);
+            self.board.raise(hole);
+            self.garbage_left -= 1;
+            holes.push(hole);
+        }
+        if !holes.is_empty() {
+            self.events.push(Event::Garbage(holes));
+        }
+    }
+
+    /// The heap has reached the buffer rows above the field. Whether that is the end is up to the mode,
+    /// otherwise rows are taken away from the top of the heap (at least down to the field) and the game goes on
+    fn top_out(&mut self) {
+        let rows = match self.settings.mode.top_out(&self.settings) {
+            TopOut::End => {
+                self.game_over = true;
+                self.events.push(Event::GameOver);
+                return;
+            }
+            TopOut::ClearTop => self.settings.zen_clear_rows,
+            TopOut::Reset => self.board.height() as usize,
+        };
+        self.stats.top_outs += 1;
+        self.events.push(Event::ToppedOut);
+
+        let (width, height) = (self.board.width(), self.board.height());
+        let top = (0..height).find(|&y| (0..width).any(|x| self.board.get(x, y) == HEAP)).unwrap_or(height);
+        let bottom = (top + rows.min(height as usize) as i32 - 1).max(self.settings.start_pos.1 - 1).min(height - 1);
+        if bottom < top {
+            return;
+        }
+        // Removing the lowest of the rows each time brings the next one down into its place
+        for _ in top..=bottom {
+            self.board.remove_row(bottom);
+        }
+        self.events.push(Event::LinesCleared(vec![bottom; (bottom - top + 1) as usize]));
+    }
+
+    /// The mode's goal is done, and so is the game
+    fn finish(&mut self) {
+        self.finished = true;
+        self.game_over = true;
+        self.events.push(Event::Finished);
+    }
+
+    /// Does clearing lines raise the level? Not in an Ultra, unless the settings say so
+    fn levels_up(&self) -> bool {
+        self.settings.mode != Mode::Ultra || self.settings.ultra_levels
+    }
+
+    /// Take the next tetromino from the queue, and top up the queue
+    fn next_type(&mut self) -> TetrominoType {
+        let tetromino_type = self.randomizer.next(&mut self.rng);
+        self.next.push_back(tetromino_type);
+        self.next.pop_front().unwrap_or(tetromino_type)
+    }
+
+    /// Make sure the queue has as many tetrominoes as we show
+    fn fill_queue(&mut self) {
+        while self.next.len() < self.settings.preview {
+            let tetromino_type = self.randomizer.next(&mut self.rng);
+            self.next.push_back(tetromino_type);
+        }
+    }
+
+    /// Check for full rows on the heap, then adjust score, level and gravity
+    fn clear_rows(&mut self, t_spin: Option<TSpin>) {
+        let board = &self.board;
+        let garbage = (0..board.height()).filter(|&y| board.is_row_full(y) && board.is_garbage(y)).count();
+        self.stats.garbage_lines += garbage;
+        let cleared = self.board.clear_full_rows();
+        let full_rows = cleared.len();
+
+        // A combo runs for as long as every tetromino clears something
+        let combo = if full_rows > 0 {
+            self.combo.map_or(0, |combo| combo + 1)
+        } else {
+            self.combo = None;
+            if t_spin.is_none() {
+                return;
+            }
+            0
+        };
+        if full_rows > 0 {
+            self.combo = Some(combo);
+            self.events.push(Event::LinesCleared(cleared));
+        }
+
+        let mut clear = Clear {
+            lines: full_rows,
+            t_spin,
+            combo,
+            back_to_back: false,
+            perfect: full_rows > 0 && self.board.is_empty(),
+            points: 0,
+        };
+        // Only clearing lines starts or breaks a back-to-back, a T-spin without lines leaves it as it was
+        if full_rows > 0 {
+            clear.back_to_back = self.back_to_back && clear.is_difficult();
+            self.back_to_back = clear.is_difficult();
+        }
+
+        let mut points = match (t_spin, full_rows) {
+            (None, 1) => 100,
+            (None, 2) => 300,
+            (None, 3) => 500,
+            (None, 4) => 800,
+            (None, x) => x * 300, // What? more than four shouldn't happen
+            (Some(TSpin::Mini), 0) => 100,
+            (Some(TSpin::Mini), 1) => 200,
+            (Some(TSpin::Mini), _) => 400,
+            (Some(TSpin::Full), 0) => 400,
+            (Some(TSpin::Full), 1) => 800,
+            (Some(TSpin::Full), 2) => 1200,
+            (Some(TSpin::Full), _) => 1600,
+        } * self.level;
+        if clear.back_to_back {
+            points = points * 3 / 2;
+        }
+        points += 50 * combo * self.level;
+        if clear.perfect {
+            points += match full_rows {
+                1 => 800,
+                2 => 1200,
+                3 => 1800,
+                _ if clear.back_to_back => 3200,
+                _ => 2000,
+            } * self.level;
+        }
+        clear.points = points;
+        self.score += points;
+
+        self.stats.lines += full_rows;
+        if self.settings.mode == Mode::Master {
+            self.master_level = (self.master_level + full_rows).min(master::MAX_LEVEL); // lines get past a section stop
+        }
+        while self.splits.len() < self.stats.lines / Settings::SPLIT_LINES {
+            self.splits.push(self.time);
+        }
+        if full_rows >= 4 {
+            self.stats.tetrises += 1;
+        }
+        if t_spin.is_some() {
+            self.stats.t_spins += 1;
+        }
+        self.stats.max_combo = self.stats.max_combo.max(combo);
+        if clear.perfect {
+            self.stats.perfect_clears += 1;
+        }
+        self.events.push(Event::Scored(clear));
+
+        // Adjust level (need 10 * level to advance)
+        self.lines_cleared += full_rows;
+        if self.lines_cleared >= self.level * 10 && self.levels_up() {
+            self.level = min(self.level + 1, self.settings.max_level);
+            self.lines_cleared = 0; // This discards any excess rows over the level threshold - eg from a multi-row clearance. Rules are unclear here.
+            self.drop_speed = drop_speed(self.level);
+        }
+        // Nintendo scoring:  1=40 * (n + 1),  2=100 * (n + 1), 3=300 * (n + 1), 4=1200 * (n + 1)  where n=level
+        // plus 1 point per soft drop space (not level dependent)
+        // 'Guideline' scoring:  1=100 * (n + 1),  2=300 * (n + 1), 3=500 * (n + 1), 4=800 * (n + 1)  where n=level
+        // plus 1 point per soft drop space and 2 per hard drop space (not level dependent, applied in soft_drop() and hard_drop() )
+        // T-spins:  0=400 * n, 1=800 * n, 2=1200 * n, 3=1600 * n  and minis:  0=100 * n, 1=200 * n, 2=400 * n
+        // Back-to-back: a Tetris or T-spin (with lines) straight after another one scores 1.5 times as much
+        // Combos: 50 * c * n more for the c-th clear in a row after the first
+        // Perfect clears (nothing left on the heap): 1=800 * n, 2=1200 * n, 3=1800 * n, 4=2000 * n (3200 * n back-to-back) more
+    }
+}
@@ -0,0 +1,25 @@
+//! The rules of the game, independent of Bevy (or any other engine).
+//!
+//! Everything needed to play a game lives here: the playing grid (`board`), the tetromino shapes
+//! (`tetromino`), how they rotate (`srs`), how they are chosen (`randomizer`), the game state with its
+//! step/apply-input API and scoring (`game`), the kinds of game and their goals (`mode`), the timing of a
+//! Master game (`master`), recordings of games that can be played back (`replay`), the timing of keys that
+//! repeat when held (`autorepeat`), the settings read from a file (`config`), which keys do what (`bindings`)
+//! and the best games so far (`scores`).
+//!
+//! The Bevy application in `main.rs` is a thin view over this: it turns key presses into `Action`s,
+//! feeds them in, and then moves block entities around to match the `Event`s that come out.
+//! Because nothing in here needs a window (or a GPU), games can be unit-tested and simulated anywhere.
+
+pub mod autorepeat;
+pub mod bindings;
+pub mod board;
+pub mod config;
+pub mod game;
+pub mod master;
+pub mod mode;
+pub mod randomizer;
+pub mod replay;
+pub mod scores;
+pub mod srs;
+pub mod tetromino;
@@ -1,114 +1,348 @@
 use bevy::app::AppExit;
+use bevy::ecs::event::Events;
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
 use bevy::window::*;
 
-use rand::{
-    distributions::{Distribution, Standard},
-    Rng,
-};
-use std::cmp::min;
 use std::cmp::Ordering;
-use std::time::Duration;
+use std::fs;
+use std::io::ErrorKind;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tetris::autorepeat::AutoRepeat;
+use tetris::bindings::{Bindings, BindingsError, Control};
+use tetris::config::{ConfigError, GameConfig};
+use tetris::game::{self, Action, Event as GameEvent, Game, Settings};
+use tetris::master;
+use tetris::mode::Mode;
+use tetris::replay::{Frame, Input as ReplayInput, Player, Replay};
+use tetris::scores::{self, Entry, HighScores, Ranking, ScoresError};
+use tetris::tetromino::TetrominoType;
 
 // ========================================
 // Constants
-// todo: most of these should be config parameters
+// Everything that can be tuned is in GameConfig (tetris::config), read from tetris.toml or --config <file>
+
+/// The configuration file used when --config doesn't name one. It doesn't have to exist
+const CONFIG_FILE: &str = "tetris.toml";
+
+/// The key bindings file used when --bindings doesn't name one. It doesn't have to exist until a key is rebound
+const BINDINGS_FILE: &str = "bindings.toml";
+
+/// The high score file used when --scores doesn't name one. It doesn't have to exist until a game makes the table.
+/// Modes other than Marathon have their own file alongside it, eg highscores-sprint.toml
+const SCORES_FILE: &str = "highscores.toml";
+
+/// The longest name that can be typed in for a high score
+const NAME_LENGTH: usize = 12;
+
+/// How many of the high scores the game over menu shows (the high scores screen shows them all)
+const GAME_OVER_SCORES: usize = 5;
+
+/// Every key that can be bound, named in the bindings file as they are here (eg "Left", "Space", "Key1")
+#[rustfmt::skip]
+const KEYS: &[KeyCode] = &[
+    KeyCode::A, KeyCode::B, KeyCode::C, KeyCode::D, KeyCode::E, KeyCode::F, KeyCode::G, KeyCode::H, KeyCode::I,
+    KeyCode::J, KeyCode::K, KeyCode::L, KeyCode::M, KeyCode::N, KeyCode::O, KeyCode::P, KeyCode::Q, KeyCode::R,
+    KeyCode::S, KeyCode::T, KeyCode::U, KeyCode::V, KeyCode::W, KeyCode::X, KeyCode::Y, KeyCode::Z,
+    KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4, KeyCode::Key5,
+    KeyCode::Key6, KeyCode::Key7, KeyCode::Key8, KeyCode::Key9, KeyCode::Key0,
+    KeyCode::Left, KeyCode::Up, KeyCode::Right, KeyCode::Down,
+    KeyCode::Space, KeyCode::Return, KeyCode::Escape, KeyCode::Back, KeyCode::Tab,
+    KeyCode::Insert, KeyCode::Delete, KeyCode::Home, KeyCode::End, KeyCode::PageUp, KeyCode::PageDown,
+    KeyCode::LShift, KeyCode::RShift, KeyCode::LControl, KeyCode::RControl, KeyCode::LAlt, KeyCode::RAlt,
+    KeyCode::Apostrophe, KeyCode::Backslash, KeyCode::Comma, KeyCode::Equals, KeyCode::Grave, KeyCode::LBracket,
+    KeyCode::RBracket, KeyCode::Minus, KeyCode::Period, KeyCode::Semicolon, KeyCode::Slash,
+    KeyCode::Numpad0, KeyCode::Numpad1, KeyCode::Numpad2, KeyCode::Numpad3, KeyCode::Numpad4,
+    KeyCode::Numpad5, KeyCode::Numpad6, KeyCode::Numpad7, KeyCode::Numpad8, KeyCode::Numpad9,
+    KeyCode::NumpadAdd, KeyCode::NumpadSubtract, KeyCode::NumpadMultiply, KeyCode::NumpadDivide,
+    KeyCode::NumpadDecimal, KeyCode::NumpadEnter,
+    KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4, KeyCode::F5, KeyCode::F6,
+    KeyCode::F7, KeyCode::F8, KeyCode::F9, KeyCode::F10, KeyCode::F11, KeyCode::F12,
+];
+
+/// Every gamepad button that can be bound, named in the bindings file as they are here (eg "DPadLeft", "South")
+#[rustfmt::skip]
+const BUTTONS: &[GamepadButtonType] = &[
+    GamepadButtonType::DPadUp, GamepadButtonType::DPadDown, GamepadButtonType::DPadLeft, GamepadButtonType::DPadRight,
+    GamepadButtonType::South, GamepadButtonType::East, GamepadButtonType::North, GamepadButtonType::West,
+    GamepadButtonType::C, GamepadButtonType::Z,
+    GamepadButtonType::LeftTrigger, GamepadButtonType::LeftTrigger2,
+    GamepadButtonType::RightTrigger, GamepadButtonType::RightTrigger2,
+    GamepadButtonType::Select, GamepadButtonType::Start, GamepadButtonType::Mode,
+    GamepadButtonType::LeftThumb, GamepadButtonType::RightThumb,
+];
 
-/// A structure to contain configuration settings that vary between debug and release builds
-struct Global {}
+// ========================================
+// Components
 
-/// Debug
-#[cfg(debug_assertions)]
-impl Global {
-    /// The space between blocks in pixels
-    const BLOCK_SPACE: f32 = 1.0;
+/// Marker for the timer that automatically drops the active tetromino every x seconds
+#[derive(Component)]
+struct SoftDropTimer(Timer);
+
+/// The keys that repeat while they are held (DAS/ARR)
+struct HeldKeys {
+    left: AutoRepeat,
+    right: AutoRepeat,
+    down: AutoRepeat,
+}
 
-    /// The score background (RGBA)
-    const SCOREFIELD_COLOR: (f32, f32, f32, f32) = (0.1, 0.0, 0.0, 0.5); 
+/// The kind of the last clear (eg 'Tetris'), any banner over the playing field (eg 'Perfect Clear'), and how long they have been shown
+struct Announcement {
+    text: String,
+    banner: String,
+    timer: Timer,
+}
 
-    /// Should we display the grid lines?
-    const DRAW_GRID: bool = true;
+/// Marker for blocks that have moved and need their sprites relocated
+#[derive(Component)]
+struct UpdateBlock;
 
-    /// Slow down the automatic drop by this factor
-    const DROP_SPEED_FACTOR: f32 = 2.0; 
+/// Marker for the blocks shown in the hold panel
+#[derive(Component, Clone, Copy)]
+struct HoldBlock;
 
-}
+/// Marker for the blocks shown in the next panel
+#[derive(Component, Clone, Copy)]
+struct NextBlock;
 
-/// Release 
-#[cfg(not(debug_assertions))]
-impl Global {
-    const BLOCK_SPACE: f32 = 0.0;
-    const SCOREFIELD_COLOR: (f32, f32, f32, f32) = (0.1, 0.0, 0.0, 0.0); // Note 0.0 alpha = clear
-    const DRAW_GRID: bool = false;
-    const DROP_SPEED_FACTOR: f32 = 1.0; 
+/// Marker for the blocks of the ghost, showing where the current tetromino will land
+#[derive(Component)]
+struct GhostBlock;
+
+/// The ghost blocks, which must be kept apart from the current tetromino and heap queries
+type GhostOnly = (With<GhostBlock>, Without<CurrentTetromino>, Without<Heap>);
+
+/// Any of the blocks outside the playing grid (hold and next panels)
+type PanelBlocks = Or<(With<HoldBlock>, With<NextBlock>)>;
+
+/// Where we are in the application. Each system is scheduled for the states it belongs to
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum AppState {
+    MainMenu,   // where we start, choosing what to do
+    Playing,    // a game is going
+    Paused,     // on top of Playing, with the pause menu
+    GameOver,   // the game (or the replay) has ended, with the game over menu
+    Settings,   // the key bindings, on top of wherever they were opened from
+    HighScores, // on top of the menu they were opened from
 }
 
-/// Common
-impl Global {
-    /// The overall background, mostly overwritten by later elements (RGBA)
-    const BOARD_COLOR: (f32, f32, f32, f32) = (0.1, 0.0, 0.2, 0.5);
+impl AppState {
+    /// The choices in this state's menu, if it has one
+    fn menu(self) -> Vec<(&'static str, MenuItem)> {
+        match self {
+            AppState::MainMenu => {
+                // A choice for each mode, to play it
+                let mut items: Vec<(&'static str, MenuItem)> =
+                    Mode::ALL.iter().map(|&mode| (mode.name(), MenuItem::Play(mode))).collect();
+                items.push(("High scores", MenuItem::HighScores));
+                items.push(("Settings", MenuItem::Settings));
+                items.push(("Quit", MenuItem::Quit));
+                items
+            }
+            AppState::Paused => vec![
+                ("Resume", MenuItem::Resume),
+                ("Restart", MenuItem::NewGame),
+                ("Settings", MenuItem::Settings),
+                ("Main menu", MenuItem::MainMenu),
+                ("Quit", MenuItem::Quit),
+            ],
+            AppState::GameOver => vec![
+                ("Play again", MenuItem::NewGame),
+                ("High scores", MenuItem::HighScores),
+                ("Main menu", MenuItem::MainMenu),
+                ("Quit", MenuItem::Quit),
+            ],
+            AppState::Playing | AppState::Settings | AppState::HighScores => Vec::new(),
+        }
+    }
+}
 
-    /// The main 10x22 grid background (RGBA)
-    const FIELD_COLOR: (f32, f32, f32, f32) = (0.2, 0.2, 0.2, 0.5);
+/// Something that can be chosen from a menu
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum MenuItem {
+    Play(Mode), // a new game of this mode
+    NewGame,    // of the same mode again
+    Resume,
+    HighScores,
+    Settings,
+    MainMenu,
+    Quit,
+}
 
-    /// The grid lines - if they are displayed at all (RGBA)
-    const GRID_COLOR: (f32, f32, f32, f32) = (0.2, 0.5, 0.2, 0.5);
+/// The keys (and gamepad buttons) that work the menus and screens. They are fixed, so rebinding can't lose them
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum MenuInput {
+    Up,
+    Down,
+    Left,
+    Right,
+    Choose,
+    Back,
+}
 
-    /// The borders around the field colour (RGBA)
-    const BORDER_COLOR: (f32, f32, f32, f32) = (1.0, 1.0, 1.0, 0.4);
+/// A menu on the screen: anything shown above it (eg the high scores at game over), the choices, and which one is selected
+#[derive(Component)]
+struct Menu {
+    heading: String,
+    items: Vec<(&'static str, MenuItem)>,
+    selected: usize,
+}
 
-    /// The border size in pixels
-    const BORDER_SIZE: f32 = 6.0;
+/// Marker for the text of a menu
+#[derive(Component)]
+struct MenuText;
+
+/// Which keys and gamepad buttons do what, as Bevy's codes, and the file they are saved in
+struct KeyBindings {
+    bindings: Bindings,
+    path: PathBuf,
+    codes: Vec<(Control, Vec<KeyCode>)>,
+    buttons: Vec<(Control, Vec<GamepadButtonType>)>,
+}
 
-    /// Width of the playing grid in blocks
-    const FIELD_WIDTH: i32 = 10;
+/// The kind of game chosen in the main menu, played until another one is chosen
+struct GameMode(Mode);
 
-    /// Height of the playing grid in blocks
-    const FIELD_HEIGHT: i32 = 20;
+/// A mode's high score table, and the file it is saved in
+struct ScoreTable {
+    scores: HighScores,
+    path: PathBuf,
+}
 
-    /// Size of each block in pixels
-    const BLOCK_SIZE: f32 = 16.0;
+/// The high score tables of every mode, in the same order as Mode::ALL
+struct ScoreTables(Vec<ScoreTable>);
 
-    /// Where the tetrominos start relative to the field and tetronimo size
-    const START_POS: (i32, i32) = (4, 4);
+impl ScoreTables {
+    fn get(&self, mode: Mode) -> &ScoreTable {
+        &self.0[ScoreTables::index(mode)]
+    }
 
-    /// The score label 'Score:' (RGBA)
-    const SCORELABEL_COLOR: (f32, f32, f32, f32) = (1.0, 1.0, 1.0, 1.0);
+    fn get_mut(&mut self, mode: Mode) -> &mut ScoreTable {
+        &mut self.0[ScoreTables::index(mode)]
+    }
 
-    /// The score text (RGBA)
-    const SCORE_COLOR: (f32, f32, f32, f32) = (1.0, 0.50, 1.0, 1.0);
+    fn index(mode: Mode) -> usize {
+        Mode::ALL.iter().position(|&other| other == mode).unwrap_or_default()
+    }
+}
 
-    /// The size of the score block
-    const SCORE_SIZE: (f32, f32) = (100.0, 25.0);
+/// The split times of the personal best for the game being played, to race against (a Sprint)
+struct Rival(Vec<Duration>);
 
-    /// relative position of the score block from middle.right of field (in blocks)
-    const SCORE_SPACE: (f32, f32) = (2.0, -5.0);
+/// Which mode's table the high scores screen is showing
+struct ScoresScreen(Mode);
 
-    /// Size of the status label in pixels
-    const STATUSLABEL_SIZE: f32 = 50.0;
+/// A game that has just made the high score table, while its player types in their name
+struct NewScore {
+    mode: Mode,
+    entry: Entry,
+    rank: usize,  // where it goes in the table, 0 = the top
+    typing: bool, // the name isn't in yet. Once it is, so is the game
+    ready: bool,  // anything typed during the game has been forgotten
+}
 
-    /// The status label (Paused / game over etc) (RGBA)
-    const STATUSLABEL_COLOR: (f32, f32, f32, f32) = (1.0, 0.5, 0.5, 0.5);
+/// The bindings on the settings screen, while it is open
+#[derive(Default)]
+struct Rebinding {
+    selected: usize, // which of Control::ALL
+    waiting: bool,   // for a key to add to the selected control
+    message: String, // eg what a key is already used for
+    changed: bool,   // save them when the screen closes
+}
 
-    /// Maximum level we allow
-    const MAX_LEVEL: usize = 20;
+/// The keyboard and gamepads as the game sees them: the keys and buttons, and what they are bound to
+#[derive(SystemParam)]
+struct Controls<'w, 's> {
+    keyboard_input: ResMut<'w, Input<KeyCode>>,
+    button_input: ResMut<'w, Input<GamepadButton>>,
+    gamepads: Res<'w, Gamepads>, // only the ones connected now, so unplugging one forgets anything it was holding
+    key_bindings: Res<'w, KeyBindings>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
 }
 
+impl<'w, 's> Controls<'w, 's> {
+    /// Is any key or button for this control down?
+    fn pressed(&self, control: Control) -> bool {
+        self.key_bindings.pressed(&self.keyboard_input, control)
+            || self.buttons(control).any(|button| self.button_input.pressed(button))
+    }
 
-// ========================================
-// Components
+    /// Has a key or button for this control just gone down?
+    fn just_pressed(&self, control: Control) -> bool {
+        self.key_bindings.just_pressed(&self.keyboard_input, control)
+            || self.buttons(control).any(|button| self.button_input.just_pressed(button))
+    }
 
-/// Marker for the timer that automatically drops the active tetromino every x seconds
-#[derive(Component)]
-struct SoftDropTimer(Timer);
+    /// Which way the menus are being worked: the arrow keys, Return (or Space) and Escape, or the d-pad, South and East
+    fn menu_input(&self) -> Option<MenuInput> {
+        let keys = |keys: &[KeyCode]| keys.iter().any(|&key| self.keyboard_input.just_pressed(key));
+        let buttons = |types: &[GamepadButtonType]| self.any_button(types);
+        if keys(&[KeyCode::Up]) || buttons(&[GamepadButtonType::DPadUp]) {
+            Some(MenuInput::Up)
+        } else if keys(&[KeyCode::Down]) || buttons(&[GamepadButtonType::DPadDown]) {
+            Some(MenuInput::Down)
+        } else if keys(&[KeyCode::Left]) || buttons(&[GamepadButtonType::DPadLeft]) {
+            Some(MenuInput::Left)
+        } else if keys(&[KeyCode::Right]) || buttons(&[GamepadButtonType::DPadRight]) {
+            Some(MenuInput::Right)
+        } else if keys(&[KeyCode::Return, KeyCode::Space]) || buttons(&[GamepadButtonType::South]) {
+            Some(MenuInput::Choose)
+        } else if keys(&[KeyCode::Escape]) || buttons(&[GamepadButtonType::East]) {
+            Some(MenuInput::Back)
+        } else {
+            None
+        }
+    }
 
-/// Marker for blocks that have moved and need their sprites relocated
+    /// Has any of these buttons just gone down, on any connected gamepad?
+    fn any_button(&self, types: &[GamepadButtonType]) -> bool {
+        self.gamepads.iter().any(|&gamepad| {
+            types
+                .iter()
+                .any(|&button_type| self.button_input.just_pressed(GamepadButton(gamepad, button_type)))
+        })
+    }
+
+    /// Forget what has just been pressed, once it has changed the state.
+    /// The systems of the new state run in the same frame, and would otherwise take it as meant for them too
+    fn consume(&mut self) {
+        let keys: Vec<KeyCode> = self.keyboard_input.get_just_pressed().copied().collect();
+        for key in keys {
+            self.keyboard_input.clear_just_pressed(key);
+        }
+        let buttons: Vec<GamepadButton> = self.button_input.get_just_pressed().copied().collect();
+        for button in buttons {
+            self.button_input.clear_just_pressed(button);
+        }
+    }
+
+    /// The buttons for this control on every connected gamepad
+    fn buttons(&self, control: Control) -> impl Iterator<Item = GamepadButton> + '_ {
+        let types = self.key_bindings.buttons(control);
+        self.gamepads
+            .iter()
+            .flat_map(move |&gamepad| types.iter().map(move |&button_type| GamepadButton(gamepad, button_type)))
+    }
+}
+
+/// Marker for a screen over everything else (settings, high scores), and the text on it
 #[derive(Component)]
-struct UpdateBlock;
+struct Screen;
 
-/// Marker to trigger game restart
 #[derive(Component)]
-struct Restart;
+struct ScreenText;
+
+/// Where the inputs of the current game are being recorded (--record)
+struct Recording {
+    path: PathBuf,
+    replay: Replay,
+}
+
+/// The replay being played back instead of reading the keyboard (--replay)
+struct Playback(Player);
 
 /// Marker for text UI elements that need to be removed/recreated when the screen size changes
 #[derive(Component)]
@@ -126,6 +360,14 @@ enum TextTypes {
     Score = 1,
     Status = 2,
     Level = 3,
+    Hold = 4,
+    Next = 5,
+    Clear = 6,
+    Streak = 7,
+    Banner = 8,
+    Time = 9,
+    Countdown = 10,
+    Gravity = 11,
     //TEST = 99,
 }
 // An enum, because we want to avoid id collisions
@@ -137,25 +379,16 @@ struct Block {
 }
 
 /// The shared game state
+///
+/// The rules live in `game`, everything else here is about how we draw it
 #[derive(Component, Debug)]
 struct Matrix {
-    width: i32,
-    full_height: i32, // probably don't need both full_height AND max_ypos
-    array_size: usize,
-    max_ypos: i32,
+    game: Game,
     field_width: f32,
     field_height: f32,
     height_offset: f32,
-    create: bool,
-    active: bool,
-    occupation: Vec<i8>, // [(y * width) + x] = occupation (0=open, 1=current, 2=heap)
-    score: usize,
-    level: usize,
-    lines_cleared: usize,
-    drop_rows: usize,
-    drop_speed: f32,
-    falling: bool,
-    game_over: bool,
+    show_ghost: bool,
+    gravity: Option<usize>, // the level of the automatic drop chosen by hand (a Zen), in place of the game's level
 }
 
 /// The block's position within the game field
@@ -167,14 +400,9 @@ struct MatrixPosition {
 
 /// The blocks that make up each tetromino.
 ///
-/// The block configuration is defined in the BLOCK_INDICIES const
+/// The block configuration is defined in the TetrominoType::BLOCK_INDICES const
 /// The block coloues are in COLORS (RGB values)
-/// The block bounding box sizes are in SIZES
-#[derive(Debug, Component)]
-struct Tetromino {
-    tetromino_type: TetrominoType,
-    index: MatrixPosition,
-}
+struct Tetromino;
 
 /// Marker for blocks of the current tetromino
 #[derive(Component)]
@@ -184,76 +412,74 @@ struct CurrentTetromino;
 #[derive(Component)]
 struct Heap;
 
+/// Marker for heap blocks that came in as garbage (a Dig), rather than from a tetromino
+#[derive(Component)]
+struct Garbage;
+
 // ========================================
 // Structures and Enums
 
-/// The different types of tetromino we can have
-#[derive(Copy, Clone, Debug, Component)]
-enum TetrominoType {
-    I = 0,
-    O = 1,
-    T = 2,
-    S = 3,
-    Z = 4,
-    L = 5,
-    J = 6,
+/// Options from the command line:
+/// `tetris [--config <file>] [--bindings <file>] [--scores <file>] [--seed <number>] [--record <file>] [--replay <file>]`
+#[derive(Debug, Default)]
+struct Arguments {
+    /// Read the configuration from this file rather than tetris.toml
+    config: Option<PathBuf>,
+
+    /// Read and save the key bindings in this file rather than bindings.toml
+    bindings: Option<PathBuf>,
+
+    /// Read and save the high scores in this file rather than highscores.toml
+    scores: Option<PathBuf>,
+
+    /// Play every game with this seed, so the tetrominoes come in the same order
+    seed: Option<u64>,
+
+    /// Save the inputs of each game to this file
+    record: Option<PathBuf>,
+
+    /// Play back the game saved in this file rather than reading the keyboard
+    replay: Option<PathBuf>,
 }
 
-/// The blocks within each type of tetromino
-/// Initial presentation is 'flat side down' as per guidelines
-impl Tetromino {
-    const BLOCK_INDICES: [[(i32, i32); 4]; 7] = [
-        [
-            // line, cyan
-            (3, 3),
-            (2, 3),
-            (1, 3),
-            (0, 3),
-        ],
-        [
-            // square, yellow
-            (0, 0),
-            (0, 1),
-            (1, 0),
-            (1, 1),
-        ],
-        [
-            // T, purple
-            (0, 2),
-            (1, 2),
-            (2, 2),
-            (1, 1),
-        ],
-        [
-            // Z, red
-            (0, 2),
-            (1, 2),
-            (1, 1),
-            (2, 1),
-        ],
-        [
-            // S, green
-            (2, 2),
-            (1, 2),
-            (1, 1),
-            (0, 1),
-        ],
-        [
-            // L, blue
-            (2, 1),
-            (0, 2),
-            (1, 2),
-            (2, 2),
-        ],
-        [
-            // J, orange *
-            (0, 2),
-            (1, 2),
-            (2, 2),
-            (0, 1),
-        ],
-    ];
+impl Arguments {
+    /// Read the options from the command line
+    fn parse() -> Result<Arguments, String> {
+        let mut arguments = Arguments::default();
+        let mut args = std::env::args().skip(1); // the first one is the program name
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" => {
+                    arguments.config = Some(args.next().ok_or("--config needs a file name")?.into());
+                }
+                "--bindings" => {
+                    arguments.bindings = Some(args.next().ok_or("--bindings needs a file name")?.into());
+                }
+                "--scores" => {
+                    arguments.scores = Some(args.next().ok_or("--scores needs a file name")?.into());
+                }
+                "--seed" => {
+                    let value = args.next().ok_or("--seed needs a number")?;
+                    let seed = value
+                        .parse()
+                        .map_err(|_| format!("--seed needs a number, not '{}'", value))?;
+                    arguments.seed = Some(seed);
+                }
+                "--record" => {
+                    arguments.record = Some(args.next().ok_or("--record needs a file name")?.into());
+                }
+                "--replay" => {
+                    arguments.replay = Some(args.next().ok_or("--replay needs a file name")?.into());
+                }
+                other => return Err(format!("Unknown option '{}'", other)),
+            }
+        }
+        Ok(arguments)
+    }
+}
 
+/// The colours of each tetromino. The shapes come from TetrominoType
+impl Tetromino {
     /// The colours of each tetromino RGB
     const COLORS: [(f32, f32, f32); 7] = [
         (0.0, 0.7, 0.7),  // line, cyan
@@ -265,54 +491,66 @@ impl Tetromino {
         (0.9, 0.25, 0.0), // J, orange
     ];
 
-    /// The size of the bounding box
-    const SIZES: [i32; 7] = [
-        4, // line, cyan
-        2, // square, yellow
-        3, // T, purple
-        3, // Z, red
-        3, // S, green
-        3, // L, blue
-        3, // J, orange
-    ];
-
     /// A vector of all the blocks that comprise a given TetrominoType
-    fn blocks_from_type(tetromino_type: TetrominoType) -> Vec<(Block, Tetromino)> {
-        let type_usize = tetromino_type as usize;
-        let color = Tetromino::COLORS[type_usize];
+    fn blocks_from_type(tetromino_type: TetrominoType) -> Vec<Block> {
+        let color = Tetromino::COLORS[tetromino_type as usize];
 
-        Tetromino::BLOCK_INDICES[type_usize]
+        TetrominoType::BLOCK_INDICES[tetromino_type as usize]
             .iter()
-            .map(|index| {
-                (
-                    Block {
-                        color: Color::rgb(color.0, color.1, color.2),
-                    },
-                    Tetromino {
-                        index: MatrixPosition {
-                            x: index.0,
-                            y: index.1,
-                        },
-                        tetromino_type,
-                    },
-                )
+            .map(|_index| Block {
+                color: Color::rgb(color.0, color.1, color.2),
             })
             .collect()
     }
 }
 
-/// Random distribution of the types
-impl Distribution<TetrominoType> for Standard {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> TetrominoType {
-        match rng.gen_range(0, 7) {
-            0 => TetrominoType::I,
-            1 => TetrominoType::O,
-            2 => TetrominoType::T,
-            3 => TetrominoType::S,
-            4 => TetrominoType::Z,
-            5 => TetrominoType::L,
-            _ => TetrominoType::J,
-        }
+impl KeyBindings {
+    fn new(bindings: Bindings, path: PathBuf) -> KeyBindings {
+        let mut key_bindings = KeyBindings {
+            bindings,
+            path,
+            codes: Vec::new(),
+            buttons: Vec::new(),
+        };
+        key_bindings.update_codes();
+        key_bindings
+    }
+
+    /// Work out the key codes again after the bindings have changed
+    fn update_codes(&mut self) {
+        let bindings = &self.bindings;
+        self.codes = Control::ALL
+            .iter()
+            .map(|&control| (control, bindings.keys(control).iter().filter_map(|name| key_code(name)).collect()))
+            .collect();
+        self.buttons = Control::ALL
+            .iter()
+            .map(|&control| (control, bindings.buttons(control).iter().filter_map(|name| button_type(name)).collect()))
+            .collect();
+    }
+
+    fn codes(&self, control: Control) -> &[KeyCode] {
+        self.codes
+            .iter()
+            .find(|(other, _codes)| *other == control)
+            .map_or(&[], |(_control, codes)| codes)
+    }
+
+    fn buttons(&self, control: Control) -> &[GamepadButtonType] {
+        self.buttons
+            .iter()
+            .find(|(other, _buttons)| *other == control)
+            .map_or(&[], |(_control, buttons)| buttons)
+    }
+
+    /// Is any key for this control down?
+    fn pressed(&self, keyboard_input: &Input<KeyCode>, control: Control) -> bool {
+        self.codes(control).iter().any(|&key| keyboard_input.pressed(key))
+    }
+
+    /// Has a key for this control just gone down?
+    fn just_pressed(&self, keyboard_input: &Input<KeyCode>, control: Control) -> bool {
+        self.codes(control).iter().any(|&key| keyboard_input.just_pressed(key))
     }
 }
 
@@ -321,26 +559,164 @@ impl Distribution<TetrominoType> for Standard {
 
 /// The main application loop
 fn main() {
-    let min_height = (Global::BLOCK_SIZE + Global::BLOCK_SPACE) * (Global::FIELD_HEIGHT as f32 + 5.0);
+    let arguments = match Arguments::parse() {
+        Ok(arguments) => arguments,
+        Err(message) => {
+            eprintln!("{}\nUsage: tetris [--config <file>] [--bindings <file>] [--scores <file>] [--seed <number>] [--record <file>] [--replay <file>]", message);
+            std::process::exit(1);
+        }
+    };
+
+    // Check the replay before opening a window
+    let playback = arguments.replay.as_ref().map(|path| match Replay::load(path) {
+        Ok(replay) => Playback(Player::new(replay)),
+        Err(error) => {
+            eprintln!("Can't play {}: {}", path.display(), error);
+            std::process::exit(1);
+        }
+    });
+
+    // A configuration that can't be used is reported, and the game carries on with the defaults.
+    // Only a file named with --config has to be there
+    let config_path = arguments.config.clone().unwrap_or_else(|| PathBuf::from(CONFIG_FILE));
+    let config = match GameConfig::load(&config_path) {
+        Ok(config) => config,
+        Err(ConfigError::Io(error)) if error.kind() == ErrorKind::NotFound && arguments.config.is_none() => {
+            GameConfig::default()
+        }
+        Err(error) => {
+            eprintln!("Can't use {}: {}. Using the default configuration", config_path.display(), error);
+            GameConfig::default()
+        }
+    };
+
+    // The same goes for the key bindings, except they are saved when they are changed
+    let bindings_path = arguments.bindings.clone().unwrap_or_else(|| PathBuf::from(BINDINGS_FILE));
+    let bindings = match Bindings::load(&bindings_path).and_then(|bindings| {
+        bindings.check(|name| key_code(name).is_some(), |name| button_type(name).is_some())?;
+        Ok(bindings)
+    }) {
+        Ok(bindings) => bindings,
+        Err(BindingsError::Io(error)) if error.kind() == ErrorKind::NotFound => Bindings::default(),
+        Err(error) => {
+            eprintln!("Can't use {}: {}. Using the default keys", bindings_path.display(), error);
+            Bindings::default()
+        }
+    };
+
+    // Each mode has its own high scores (see load_scores for what happens if they can't be read)
+    let scores_path = arguments.scores.clone().unwrap_or_else(|| PathBuf::from(SCORES_FILE));
+    let score_tables = ScoreTables(
+        Mode::ALL
+            .iter()
+            .map(|&mode| {
+                let path = mode_scores_path(&scores_path, mode);
+                ScoreTable {
+                    scores: load_scores(&path, config.high_scores, mode.ranking()),
+                    path,
+                }
+            })
+            .collect(),
+    );
+
+    // The rules, which know nothing about sprites
+    // A replay brings its own settings (including the seed), so the game plays out the same as when it was recorded
+    let settings = match &playback {
+        Some(playback) => playback.0.replay().settings.clone(),
+        None => Settings {
+            seed: arguments.seed,
+            ..config.settings()
+        },
+    };
+
+    // Room for the field, the rows above it where tetrominoes start and a block's space below
+    let min_height = (config.block_size + config.block_space) * (settings.height + settings.start_pos.1 + 1) as f32;
 
     let mut app = App::new();
 
     app.insert_resource(bevy::window::WindowDescriptor {
         title: "Tetris".to_string(),
-        height: min_height as f32,
+        height: min_height,
         width: 600.0,
         resizable: true,
+        resize_constraints: WindowResizeConstraints {
+            min_height,
+            ..Default::default()
+        },
         ..Default::default()
     })
     .add_plugins(DefaultPlugins)
-    .insert_resource(SoftDropTimer(Timer::from_seconds(Global::DROP_SPEED_FACTOR, true))) // start speed
+    .insert_resource(arguments)
+    .insert_resource(config.clone())
+    .insert_resource(KeyBindings::new(bindings, bindings_path))
+    .insert_resource(score_tables)
+    .insert_resource(GameMode(settings.mode)) // a replay brings its own
+    .insert_resource(Rival(Vec::new()))
+    .insert_resource(settings)
+    .insert_resource(SoftDropTimer(Timer::from_seconds(config.drop_speed_factor, true))) // start speed
+    .insert_resource(Announcement {
+        text: "".to_string(),
+        banner: "".to_string(),
+        timer: Timer::from_seconds(config.announce_time, false),
+    })
+    .insert_resource(HeldKeys {
+        left: AutoRepeat::new(Duration::from_millis(config.das_ms), Duration::from_millis(config.arr_ms)),
+        right: AutoRepeat::new(Duration::from_millis(config.das_ms), Duration::from_millis(config.arr_ms)),
+        down: AutoRepeat::new(Duration::ZERO, Duration::from_secs_f32(config.drop_speed_factor) / config.soft_drop_factor), // no delay, and the rate follows the level
+    })
+    .add_event::<GameEvent>() // What happened in the game, passed from the movement system to the spawn system
+    // A replay goes straight into the game, otherwise we start at the main menu
+    .add_state(if playback.is_some() { AppState::Playing } else { AppState::MainMenu })
     .add_startup_system(tetris_setup)
     // Stages are: First, Startup, PreUpdate, Update, PostUpdate, Last
     .add_system_to_stage(CoreStage::PostUpdate, spawn_current_tetromino) // Needs to happen seperately from other systems
-    .add_system(move_current_tetromino)
+    // Playing. A new game starts each time we enter the state (not when we come back to it from the pause menu)
+    .add_system_set(SystemSet::on_enter(AppState::Playing).with_system(start_game))
+    .add_system_set(
+        SystemSet::on_update(AppState::Playing)
+            .with_system(move_current_tetromino.label("movement"))
+            .with_system(adjust_gravity.before("movement")),
+    )
+    .add_system_set(SystemSet::on_resume(AppState::Playing).with_system(resume_game))
+    // The menus, which are all the same apart from their choices
+    .add_system_set(SystemSet::on_enter(AppState::MainMenu).with_system(open_menu))
+    .add_system_set(SystemSet::on_resume(AppState::MainMenu).with_system(open_menu))
+    .add_system_set(SystemSet::on_update(AppState::MainMenu).with_system(choose_from_menu))
+    .add_system_set(SystemSet::on_pause(AppState::MainMenu).with_system(close_menu))
+    .add_system_set(SystemSet::on_exit(AppState::MainMenu).with_system(close_menu))
+    .add_system_set(SystemSet::on_enter(AppState::Paused).with_system(open_menu))
+    .add_system_set(SystemSet::on_resume(AppState::Paused).with_system(open_menu))
+    .add_system_set(SystemSet::on_update(AppState::Paused).with_system(choose_from_menu))
+    .add_system_set(SystemSet::on_pause(AppState::Paused).with_system(close_menu))
+    .add_system_set(SystemSet::on_exit(AppState::Paused).with_system(close_menu))
+    // The game over menu waits for the name of a new high score first
+    .add_system_set(SystemSet::on_enter(AppState::GameOver).with_system(open_menu).with_system(check_high_score))
+    .add_system_set(SystemSet::on_resume(AppState::GameOver).with_system(open_menu))
+    .add_system_set(
+        SystemSet::on_update(AppState::GameOver)
+            .with_system(enter_name.label("name"))
+            .with_system(choose_from_menu.after("name")),
+    )
+    .add_system_set(SystemSet::on_pause(AppState::GameOver).with_system(close_menu))
+    .add_system_set(SystemSet::on_exit(AppState::GameOver).with_system(close_menu))
+    // The screens that go over everything
+    .add_system_set(SystemSet::on_enter(AppState::Settings).with_system(open_settings))
+    .add_system_set(SystemSet::on_update(AppState::Settings).with_system(rebind_keys))
+    .add_system_set(SystemSet::on_exit(AppState::Settings).with_system(close_screen))
+    .add_system_set(SystemSet::on_enter(AppState::HighScores).with_system(open_high_scores))
+    .add_system_set(SystemSet::on_update(AppState::HighScores).with_system(browse_high_scores))
+    .add_system_set(SystemSet::on_exit(AppState::HighScores).with_system(close_screen))
+    // Whatever the state
+    .add_system(connect_gamepads.before("movement"))
     .add_system(update_block_sprites)
-    .add_system(resize_window)
-    .add_system(restart);
+    .add_system(show_lock_delay.after("movement"))
+    .add_system(end_announcement)
+    .add_system(show_mode_status.after("movement"))
+    .add_system(resize_window);
+
+    if let Some(playback) = playback {
+        app.insert_resource(playback);
+    }
 
     // Debug hierarchy inspector
     #[cfg(debug_assertions)]
@@ -353,51 +729,45 @@ fn main() {
 // Systems
 
 /// Set up the game field and internal resources
-fn tetris_setup(mut commands: Commands) {
+fn tetris_setup(mut commands: Commands, config: Res<GameConfig>, settings: Res<Settings>, arguments: Res<Arguments>) {
     // Default camera(s)
     commands.spawn_bundle(OrthographicCameraBundle::new_2d());
     commands.spawn_bundle(UiCameraBundle::default());
 
-    // Set up some size values
-    let field_width = Global::FIELD_WIDTH as f32 * (Global::BLOCK_SIZE + Global::BLOCK_SPACE) - Global::BLOCK_SPACE;
-    let field_height = Global::FIELD_HEIGHT as f32 * (Global::BLOCK_SIZE + Global::BLOCK_SPACE) - Global::BLOCK_SPACE;
-    let height_offset = Global::START_POS.1 as f32 * (Global::BLOCK_SIZE + Global::BLOCK_SPACE) / 2.0; // Move the field down this many cells to allow for the block entry area
+    // Set up some size values, the size of the field comes from the game's settings
+    let field_width = settings.width as f32 * (config.block_size + config.block_space) - config.block_space;
+    let field_height = settings.height as f32 * (config.block_size + config.block_space) - config.block_space;
+    let height_offset = settings.start_pos.1 as f32 * (config.block_size + config.block_space) / 2.0; // Move the field down this many cells to allow for the block entry area
 
-    let array_size = (Global::FIELD_WIDTH * (Global::FIELD_HEIGHT + Global::START_POS.1)) as usize;
-    let mut field_array = Vec::with_capacity(array_size);
-    for _x in 0..array_size {
-        field_array.push(0);
+    // The rules, which know nothing about sprites. The first tetromino comes when a game starts
+    let game = Game::new(settings.clone());
+
+    // Start recording, now that we know the seed
+    if let Some(path) = &arguments.record {
+        commands.insert_resource(Recording {
+            path: path.clone(),
+            replay: Replay::new(game.settings(), game.seed()),
+        });
     }
 
     // The field resource, block sizes and positions
     let matrix = Matrix {
-        width: Global::FIELD_WIDTH,
-        full_height: Global::FIELD_HEIGHT + Global::START_POS.1,
-        array_size,
-        max_ypos: Global::FIELD_HEIGHT + Global::START_POS.1 - 1,
+        game,
         field_width,
         field_height,
         height_offset,
-        create: true,
-        active: true,
-        occupation: field_array,
-        score: 0,
-        level: 1,
-        lines_cleared: 0,
-        drop_rows: 0,
-        drop_speed: 1.0,
-        falling: false,
-        game_over: false,
+        show_ghost: config.show_ghost,
+        gravity: None,
     };
 
     // Add the overall background as a sprite, centred in the window (so no transform required)
     commands.spawn().insert_bundle(SpriteBundle {
         sprite: Sprite {
             custom_size: Some(Vec2::new(
-                field_width + 2.0 * Global::BORDER_SIZE,
-                field_height + 2.0 * Global::BORDER_SIZE + 2.0 * height_offset,
+                field_width + 2.0 * config.border_size,
+                field_height + 2.0 * config.border_size + 2.0 * height_offset,
             )),
-            color: Color::rgba(Global::BOARD_COLOR.0, Global::BOARD_COLOR.1, Global::BOARD_COLOR.2, Global::BOARD_COLOR.3),
+            color: Color::from(config.board_color),
             ..Default::default() // Sprite defaults
         },
         ..Default::default() // Sprite bundle defaults
@@ -407,7 +777,7 @@ fn tetris_setup(mut commands: Commands) {
     commands.spawn_bundle(SpriteBundle {
         sprite: Sprite {
             custom_size: Some(Vec2::new(field_width, field_height)),
-            color: Color::rgba(Global::FIELD_COLOR.0, Global::FIELD_COLOR.1, Global::FIELD_COLOR.2, Global::FIELD_COLOR.3),
+            color: Color::from(config.field_color),
             ..Default::default() // Sprite defaults
         },
         transform: Transform {
@@ -421,17 +791,12 @@ fn tetris_setup(mut commands: Commands) {
     // - Left border
     commands.spawn_bundle(SpriteBundle {
         sprite: Sprite {
-            custom_size: Some(Vec2::new(Global::BORDER_SIZE, field_height)),
-            color: Color::rgba(
-                Global::BORDER_COLOR.0,
-                Global::BORDER_COLOR.1,
-                Global::BORDER_COLOR.2,
-                Global::BORDER_COLOR.3,
-            ),
+            custom_size: Some(Vec2::new(config.border_size, field_height)),
+            color: Color::from(config.border_color),
             ..Default::default() // Sprite defaults
         },
         transform: Transform {
-            translation: Vec3::new(-(field_width + Global::BORDER_SIZE) / 2.0, -height_offset, 0.0),
+            translation: Vec3::new(-(field_width + config.border_size) / 2.0, -height_offset, 0.0),
             ..Default::default()
         },
         ..Default::default() // Sprite bundle defaults
@@ -440,17 +805,12 @@ fn tetris_setup(mut commands: Commands) {
     // - Right border
     commands.spawn_bundle(SpriteBundle {
         sprite: Sprite {
-            custom_size: Some(Vec2::new(Global::BORDER_SIZE, field_height)),
-            color: Color::rgba(
-                Global::BORDER_COLOR.0,
-                Global::BORDER_COLOR.1,
-                Global::BORDER_COLOR.2,
-                Global::BORDER_COLOR.3,
-            ),
+            custom_size: Some(Vec2::new(config.border_size, field_height)),
+            color: Color::from(config.border_color),
             ..Default::default() // Sprite defaults
         },
         transform: Transform {
-            translation: Vec3::new((field_width + Global::BORDER_SIZE) / 2.0, -height_offset, 0.0),
+            translation: Vec3::new((field_width + config.border_size) / 2.0, -height_offset, 0.0),
             ..Default::default()
         },
         ..Default::default() // Sprite bundle defaults
@@ -459,19 +819,14 @@ fn tetris_setup(mut commands: Commands) {
     // - Bottom border
     commands.spawn_bundle(SpriteBundle {
         sprite: Sprite {
-            custom_size: Some(Vec2::new(field_width + 2.0 * Global::BORDER_SIZE, Global::BORDER_SIZE)),
-            color: Color::rgba(
-                Global::BORDER_COLOR.0,
-                Global::BORDER_COLOR.1,
-                Global::BORDER_COLOR.2,
-                Global::BORDER_COLOR.3,
-            ),
+            custom_size: Some(Vec2::new(field_width + 2.0 * config.border_size, config.border_size)),
+            color: Color::from(config.border_color),
             ..Default::default() // Sprite defaults
         },
         transform: Transform {
             translation: Vec3::new(
                 0.0,
-                -(field_height + Global::BORDER_SIZE) / 2.0 - height_offset,
+                -(field_height + config.border_size) / 2.0 - height_offset,
                 0.0,
             ),
             ..Default::default()
@@ -480,19 +835,19 @@ fn tetris_setup(mut commands: Commands) {
     });
 
     // Grid lines
-    if Global::DRAW_GRID {
-        for x in 1..Global::FIELD_WIDTH {
+    if config.draw_grid {
+        for x in 1..settings.width {
             commands.spawn_bundle(SpriteBundle {
                 sprite: Sprite {
-                    custom_size: Some(Vec2::new(Global::BLOCK_SPACE, field_height)),
-                    color: Color::rgba(Global::GRID_COLOR.0, Global::GRID_COLOR.1, Global::GRID_COLOR.2, Global::GRID_COLOR.3),
+                    custom_size: Some(Vec2::new(config.block_space, field_height)),
+                    color: Color::from(config.grid_color),
                     ..Default::default() // Sprite defaults
                 },
                 transform: Transform {
                     translation: Vec3::new(
-                        (field_width + Global::BORDER_SIZE) / 2.0
-                            - (x as f32 * (Global::BLOCK_SIZE + Global::BLOCK_SPACE))
-                            - Global::BLOCK_SPACE,
+                        (field_width + config.border_size) / 2.0
+                            - (x as f32 * (config.block_size + config.block_space))
+                            - config.block_space,
                         -height_offset,
                         0.0,
                     ),
@@ -502,19 +857,19 @@ fn tetris_setup(mut commands: Commands) {
             });
         }
 
-        for y in 1..Global::FIELD_HEIGHT {
+        for y in 1..settings.height {
             commands.spawn_bundle(SpriteBundle {
                 sprite: Sprite {
-                    custom_size: Some(Vec2::new(field_width, Global::BLOCK_SPACE)),
-                    color: Color::rgba(Global::GRID_COLOR.0, Global::GRID_COLOR.1, Global::GRID_COLOR.2, Global::GRID_COLOR.3),
+                    custom_size: Some(Vec2::new(field_width, config.block_space)),
+                    color: Color::from(config.grid_color),
                     ..Default::default() // Sprite defaults
                 },
                 transform: Transform {
                     translation: Vec3::new(
                         0.0,
-                        -(field_height + Global::BORDER_SIZE) / 2.0 - height_offset
-                            + (y as f32 * (Global::BLOCK_SIZE + Global::BLOCK_SPACE))
-                            + Global::BLOCK_SPACE,
+                        -(field_height + config.border_size) / 2.0 - height_offset
+                            + (y as f32 * (config.block_size + config.block_space))
+                            + config.block_space,
                         0.0,
                     ),
                     ..Default::default()
@@ -525,23 +880,18 @@ fn tetris_setup(mut commands: Commands) {
     }
 
     // Add the score background as a sprite to the right of the main field
-    let xpos = field_width / 2.0 + Global::SCORE_SPACE.0 * (Global::BLOCK_SIZE + Global::BLOCK_SPACE) + Global::SCORE_SIZE.0 / 2.0;
-    let ypos = Global::SCORE_SPACE.1 * (Global::BLOCK_SIZE + Global::BLOCK_SPACE) - Global::SCORE_SIZE.1 / 2.0;
+    let xpos = field_width / 2.0 + config.score_space.0 * (config.block_size + config.block_space) + config.score_size.0 / 2.0;
+    let ypos = config.score_space.1 * (config.block_size + config.block_space) - config.score_size.1 / 2.0;
     commands.spawn_bundle(SpriteBundle {
         sprite: Sprite {
-            custom_size: Some(Vec2::new(Global::SCORE_SIZE.0, Global::SCORE_SIZE.1)),
-            color: Color::rgba(
-                Global::SCOREFIELD_COLOR.0,
-                Global::SCOREFIELD_COLOR.1,
-                Global::SCOREFIELD_COLOR.2,
-                Global::SCOREFIELD_COLOR.3,
-            ),
+            custom_size: Some(Vec2::new(config.score_size.0, config.score_size.1)),
+            color: Color::from(config.scorefield_color),
             ..Default::default() // Sprite defaults
         },
         transform: Transform {
             translation: Vec3::new(
-                //(field_width + Global::SCORE_SIZE.0) / 2.0 + Global::SCORE_SPACE.0,
-                xpos, //-height_offset + Global::SCORE_SIZE.1 / 2.0 - Global::SCORE_SPACE.1,
+                //(field_width + config.score_size.0) / 2.0 + config.score_space.0,
+                xpos, //-height_offset + config.score_size.1 / 2.0 - config.score_space.1,
                 ypos, 0.0,
             ),
             ..Default::default()
@@ -549,129 +899,169 @@ fn tetris_setup(mut commands: Commands) {
         ..Default::default() // Sprite bundle defaults
     });
 
+    // Add the hold panel background as a sprite to the left of the main field
+    let (xpos, ypos) = hold_position(&config, &matrix);
+    commands.spawn_bundle(SpriteBundle {
+        sprite: Sprite {
+            custom_size: Some(Vec2::new(
+                config.hold_size.0 * (config.block_size + config.block_space),
+                config.hold_size.1 * (config.block_size + config.block_space),
+            )),
+            color: Color::from(config.field_color),
+            ..Default::default() // Sprite defaults
+        },
+        transform: Transform {
+            translation: Vec3::new(xpos, ypos, 0.0),
+            ..Default::default()
+        },
+        ..Default::default() // Sprite bundle defaults
+    });
+
+    // Add the next panel background as a sprite below the hold panel
+    let (xpos, top) = next_position(&config, &matrix, 0);
+    let height = config.next_count as f32 * config.next_slot * (config.block_size + config.block_space);
+    let top = top + config.next_slot * (config.block_size + config.block_space) / 2.0; // from the centre of the first slot to the top
+    commands.spawn_bundle(SpriteBundle {
+        sprite: Sprite {
+            custom_size: Some(Vec2::new(config.hold_size.0 * (config.block_size + config.block_space), height)),
+            color: Color::from(config.field_color),
+            ..Default::default() // Sprite defaults
+        },
+        transform: Transform {
+            translation: Vec3::new(xpos, top - height / 2.0, 0.0),
+            ..Default::default()
+        },
+        ..Default::default() // Sprite bundle defaults
+    });
+
     // UI components (text elements) are created in resize_window(), so that they can move with the window size
 
     // Add the specification of the field as a resource
     commands.insert_resource(matrix);
 }
 
-/// Spawn a new tetromino, check for completed rows, update the score
+/// Spawn a new tetromino, remove cleared rows, update the score
+///
+/// The game has already done all of this in its own representation, here we make the block entities match
+#[allow(clippy::too_many_arguments)] // Lots of arguments here, one query per kind of block
 fn spawn_current_tetromino(
     mut commands: Commands,
-    mut matrix: ResMut<Matrix>,
+    config: Res<GameConfig>,
+    matrix: Res<Matrix>,
     mut soft_drop_timer: ResMut<SoftDropTimer>,
+    mut game_events: EventReader<GameEvent>,
     mut heap_query: Query<(
         Entity,
         &mut MatrixPosition,
         &Heap,
         Without<CurrentTetromino>,
     )>, // all the blocks in the heap, must be exclude CurrentTetromino or we get a query conflict
+    hold_query: Query<Entity, With<HoldBlock>>,
+    next_query: Query<Entity, With<NextBlock>>,
     mut text_query: Query<(&mut Text, &TextType)>,
+    mut announcement: ResMut<Announcement>,
 ) {
-    // If we don't need to create a block, return early
-    if !matrix.create {
-        return;
-    }
-    matrix.create = false;
-    matrix.falling = false;
-    matrix.drop_rows = 0;
-
-    // Check for full rows on the heap - counting from the bottom
-    let mut y = matrix.full_height - 1;
-    //let mut first_occupied_row = y; // the higehst (lowest numbered) row that contains a block. Used for adjusting drop speed
-    let mut full_rows = 0; // number of rows filled
-    while y >= 0 {
-        let mut full_row = true;
-        for x in 0..matrix.width {
-            let address = (matrix.width * y + x) as usize;
-            if matrix.occupation[address] != 2 {
-                // All the blocks in a row must be on the heap
-                full_row = false;
-                //break; // We can't break because we have to check for the highest block
-            }
-        }
-
-        if full_row {
-            full_rows += 1;
-
-            // If I am on the row to clear, remove me and move me out of bounds so the field array check ignores me
-            // If I am above that row, move me down and mark me for update
-            for (entity, mut heap_position, _heap, _current) in heap_query.iter_mut() {
-                match heap_position.y.cmp(&y) {
-                    Ordering::Equal => {
-                        heap_position.x = -1;
-                        commands.entity(entity).despawn_recursive();
+    // Only the last tetromino spawned this frame is still there (eg after a lock and a hold), and the blocks are
+    // created where the game has it now, so any earlier ones get no entities
+    let events: Vec<&GameEvent> = game_events.iter().collect();
+    let last_spawned = events.iter().rposition(|event| matches!(event, GameEvent::Spawned(..)));
+    for (index, event) in events.into_iter().enumerate() {
+        match event {
+            GameEvent::Scored(clear) => {
+                // Tell the player what they did, for a while
+                announcement.text = clear.name();
+                announcement.banner = if clear.perfect { "Perfect Clear".to_string() } else { "".to_string() };
+                announcement.timer.reset();
+                for (mut text, text_type) in text_query.iter_mut() {
+                    match text_type.id {
+                        TextTypes::Clear => text.sections[0].value = announcement.text.clone(),
+                        TextTypes::Banner => text.sections[0].value = announcement.banner.clone(),
+                        _ => {}
                     }
-                    Ordering::Less => {
-                        heap_position.y += 1;
-                        commands.entity(entity).insert(UpdateBlock);
+                }
+            }
+            GameEvent::LinesCleared(rows) => {
+                // Rows come bottom up, each one relative to the heap after the previous row was removed
+                for &y in rows.iter() {
+                    // If I am on the row to clear, remove me and move me out of bounds so the field array check ignores me
+                    // If I am above that row, move me down and mark me for update
+                    for (entity, mut heap_position, _heap, _current) in heap_query.iter_mut() {
+                        if heap_position.x < 0 {
+                            continue; // Already removed
+                        }
+                        match heap_position.y.cmp(&y) {
+                            Ordering::Equal => {
+                                heap_position.x = -1;
+                                commands.entity(entity).despawn_recursive();
+                            }
+                            Ordering::Less => {
+                                heap_position.y += 1;
+                                commands.entity(entity).insert(UpdateBlock);
+                            }
+                            Ordering::Greater => {}
+                        }
                     }
-                    Ordering::Greater => {}
                 }
             }
-
-            // Then deal with the occupation array
-            // - move everything above me down one, only go to row 1, which becomes what's in row 0
-            // - only move heap blocks
-            for y2 in (1..=y).rev() {
-                for x in 0..matrix.width {
-                    let address_new = (matrix.width * y2 + x) as usize;
-                    let address_old = (matrix.width * (y2 - 1) + x) as usize;
-                    matrix.occupation[address_new] = matrix.occupation[address_old];
+            GameEvent::ToppedOut => {
+                // The game goes on (a Zen), the rows taken off the heap come next as LinesCleared
+                announcement.banner = "Topped out".to_string();
+                announcement.timer.reset();
+                for (mut text, text_type) in text_query.iter_mut() {
+                    if text_type.id == TextTypes::Banner {
+                        text.sections[0].value = announcement.banner.clone();
+                    }
                 }
             }
-            // - and make sure the first row is empty (of heap blocks - should never happen)
-            for x in 0..matrix.width {
-                let address_new = (x) as usize;
-                if matrix.occupation[address_new] == 2 {
-                    matrix.occupation[address_new] = 0;
+            GameEvent::Garbage(holes) => {
+                // Each row pushes the heap up one, and comes in at the bottom
+                let bottom = matrix.game.board().height() - 1;
+                for &hole in holes.iter() {
+                    for (entity, mut heap_position, _heap, _current) in heap_query.iter_mut() {
+                        if heap_position.x >= 0 {
+                            heap_position.y -= 1;
+                            commands.entity(entity).insert(UpdateBlock);
+                        }
+                    }
+                    spawn_garbage(&mut commands, &config, &matrix, bottom, hole);
                 }
             }
-        } else {
-            y -= 1; // If it wasn't a full row we can scan the next one.
-                    // We DON'T do this for full rows because the row we just moved down might be full too
-        }
-    }
+            GameEvent::Spawned(tet_type, _blocks) if Some(index) == last_spawned => {
+                spawn_blocks(&mut commands, &config, &matrix, &mut soft_drop_timer, *tet_type, &heap_query, &mut text_query);
 
-    // If we had any full rows, adjust score, level and gravity
-    //
-    if full_rows > 0 {
-        match full_rows {
-            1 => {
-                matrix.score += 100 * matrix.level;
-            }
-            2 => {
-                matrix.score += 300 * matrix.level;
-            }
-            3 => {
-                matrix.score += 500 * matrix.level;
+                // The next queue has moved along
+                for entity in next_query.iter() {
+                    commands.entity(entity).despawn();
+                }
+                for (slot, next_type) in matrix.game.next_queue().iter().enumerate() {
+                    let position = next_position(&config, &matrix, slot);
+                    spawn_panel_blocks(&mut commands, &config, *next_type, position, config.next_scale, NextBlock);
+                }
             }
-            4 => {
-                matrix.score += 800 * matrix.level;
+            GameEvent::Held(tet_type) => {
+                // Replace whatever was in the hold panel
+                for entity in hold_query.iter() {
+                    commands.entity(entity).despawn();
+                }
+                spawn_panel_blocks(&mut commands, &config, *tet_type, hold_position(&config, &matrix), 1.0, HoldBlock);
             }
-            x => {
-                matrix.score += x * 300 * matrix.level;
-            } // What? more than four shouldn't happen
-        }
-
-        // Adjust level (need 10 * level to advance)
-        matrix.lines_cleared += full_rows;
-        if matrix.lines_cleared >= matrix.level * 10 {
-            matrix.level = min(matrix.level + 1, Global::MAX_LEVEL);
-            matrix.lines_cleared = 0; // This discards any excess rows over the level threshold - eg from a multi-row clearance. Rules are unclear here.
-            matrix.drop_speed =
-                (0.8 - ((matrix.level - 1) as f32 * 0.007)).powf((matrix.level - 1) as f32);
-            //'Guideline' rule: Time = (0.8-((Level-1)*0.007))^(Level-1)
+            _ => {}
         }
     }
-    // Nintendo scoring:  1=40 * (n + 1),  2=100 * (n + 1), 3=300 * (n + 1), 4=1200 * (n + 1)  where n=level
-    // plus 1 point per soft drop space (not level dependent)
-    // 'Guideline' scoring:  1=100 * (n + 1),  2=300 * (n + 1), 3=500 * (n + 1), 4=800 * (n + 1)  where n=level
-    // plus 1 point per soft drop space (not level dependent, applied in move_current_tetromino() )
-    // also spin and combo etc - not implemented
+}
 
-    // Adjust the drop speed depending on the highest occupied row - interpolate between the two timer values
-    let timer_speed = Global::DROP_SPEED_FACTOR * matrix.drop_speed;
+/// Create the block entities for a new tetromino, and catch up with the score, level and drop speed
+fn spawn_blocks(
+    commands: &mut Commands,
+    config: &GameConfig,
+    matrix: &Matrix,
+    soft_drop_timer: &mut SoftDropTimer,
+    tet_type: TetrominoType,
+    heap_query: &Query<(Entity, &mut MatrixPosition, &Heap, Without<CurrentTetromino>)>,
+    text_query: &mut Query<(&mut Text, &TextType)>,
+) {
+    // Adjust the drop speed for the current level
+    let timer_speed = drop_interval(config, matrix);
     soft_drop_timer
         .0
         .set_duration(Duration::from_secs_f32(timer_speed));
@@ -683,67 +1073,65 @@ fn spawn_current_tetromino(
     // This block will be omitted from release builds
     #[cfg(debug_assertions)]
     {
+        use tetris::board::HEAP;
+
         // Check that the occupation array matches the blocks
         // Build an occupation array from the entities
-        //let array_size = (matrix.width * (matrix.height + Global::START_POS.1)) as usize;
-        let mut test_field_array = Vec::with_capacity(matrix.array_size);
-        for _x in 0..matrix.array_size {
-            test_field_array.push(0);
-        }
+        let board = matrix.game.board();
+        let mut test_field_array = vec![0; board.occupation().len()];
 
-        // Heap blocks - we shouldn't have any 'current' blocks at this point
+        // Heap blocks - the new tetromino doesn't have any entities yet, so only compare the heap
         for (_entity, position, _heap, _current) in heap_query.iter() {
             if position.x >= 0 {
-                let address = (matrix.width * position.y + position.x) as usize;
-                test_field_array[address] = 2;
+                let address = (board.width() * position.y + position.x) as usize;
+                test_field_array[address] = HEAP;
             }
         }
 
-        for y in 0..matrix.full_height {
-            for x in 0..matrix.width {
-                let address = (matrix.width * y + x) as usize;
-                if test_field_array[address] != matrix.occupation[address] {
+        for y in 0..board.height() {
+            for x in 0..board.width() {
+                let address = (board.width() * y + x) as usize;
+                if (test_field_array[address] == HEAP) != (board.occupation()[address] == HEAP) {
                     println!(
                         "Array mismatch @({},{}) test:{} vs matrix:{}",
-                        x, y, test_field_array[address], matrix.occupation[address]
+                        x, y, test_field_array[address], board.occupation()[address]
                     );
                 }
             }
         }
     }
+    #[cfg(not(debug_assertions))]
+    let _ = heap_query;
 
     // Update the score
     for (mut text, text_type) in text_query.iter_mut() {
         match text_type.id {
             TextTypes::Score => {
-                text.sections[1].value = format!(" {:07}", matrix.score);
+                text.sections[1].value = format!(" {:07}", matrix.game.score());
             }
             TextTypes::Level => {
-                text.sections[1].value = format!(" {:02}", matrix.level);
+                text.sections[1].value = level_text(&matrix.game);
+            }
+            TextTypes::Streak => {
+                text.sections[0].value = streak_text(&matrix.game);
             }
             _ => {}
         }
     }
 
-    // Create a new tetromino
-    // TODO: random rotation, random horizontal position?
-    let tet_type: TetrominoType = rand::random();
+    // Create the blocks where the game has the current tetromino now - it may have moved since it appeared
+    let piece = match matrix.game.piece() {
+        Some(piece) if piece.tetromino_type == tet_type => piece,
+        _ => return,
+    };
     let blocks = Tetromino::blocks_from_type(tet_type);
-    for block in blocks.into_iter() {
-        let tetromino_matrix_size = Tetromino::SIZES[block.1.tetromino_type as usize];
-        let (xpos, ypos) = grid_position(
-            &matrix,
-            Global::START_POS.0 + block.1.index.x,
-            Global::START_POS.1 - tetromino_matrix_size + block.1.index.y,
-        );
-        let address = (matrix.width * (Global::START_POS.1 - tetromino_matrix_size + block.1.index.y)
-            + (Global::START_POS.0 + block.1.index.x)) as usize;
-        matrix.occupation[address] = 1;
+    for (block, &(x, y)) in blocks.into_iter().zip(piece.blocks.iter()) {
+        let (xpos, ypos) = grid_position(config, matrix, x, y);
 
         let mut tet = commands.spawn_bundle(SpriteBundle {
             sprite: Sprite {
-                custom_size: Some(Vec2::new(Global::BLOCK_SIZE, Global::BLOCK_SIZE)),
-                color: Color::rgb(block.0.color.r(), block.0.color.g(), block.0.color.b()),
+                custom_size: Some(Vec2::new(config.block_size, config.block_size)),
+                color: block.color,
                 ..Default::default() // Sprite defaults
             },
             transform: Transform::from_translation(Vec3::new(xpos, ypos, 1.0)),
@@ -751,12 +1139,84 @@ fn spawn_current_tetromino(
         });
 
         tet.insert(CurrentTetromino);
-        tet.insert(MatrixPosition {
-            // the starting position of the BLOCK in the game field - starts in the top buffer
-            x: Global::START_POS.0 + block.1.index.x,
-            y: Global::START_POS.1 - tetromino_matrix_size + block.1.index.y,
-        });
-        tet.insert(tet_type);
+        tet.insert(MatrixPosition { x, y });
+        tet.insert(block);
+    }
+
+    if matrix.show_ghost {
+        spawn_ghost(commands, config, matrix);
+    }
+}
+
+/// Create the block entities of a garbage row, full apart from the hole. They go straight on the heap
+fn spawn_garbage(commands: &mut Commands, config: &GameConfig, matrix: &Matrix, y: i32, hole: i32) {
+    let color = Color::from(config.garbage_color);
+    for x in (0..matrix.game.board().width()).filter(|&x| x != hole) {
+        let (xpos, ypos) = grid_position(config, matrix, x, y);
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    custom_size: Some(Vec2::new(config.block_size, config.block_size)),
+                    color,
+                    ..Default::default() // Sprite defaults
+                },
+                transform: Transform::from_translation(Vec3::new(xpos, ypos, 1.0)),
+                ..Default::default() // Sprite bundle defaults
+            })
+            .insert(Heap)
+            .insert(Garbage)
+            .insert(MatrixPosition { x, y })
+            .insert(Block { color });
+    }
+}
+
+/// Create heap block entities for a tetromino that locked without ever having any (see `move_current_tetromino`)
+fn spawn_heap_blocks(
+    commands: &mut Commands,
+    config: &GameConfig,
+    matrix: &Matrix,
+    tet_type: TetrominoType,
+    blocks: &[(i32, i32); 4],
+) {
+    for (block, &(x, y)) in Tetromino::blocks_from_type(tet_type).into_iter().zip(blocks.iter()) {
+        let (xpos, ypos) = grid_position(config, matrix, x, y);
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    custom_size: Some(Vec2::new(config.block_size, config.block_size)),
+                    color: block.color,
+                    ..Default::default() // Sprite defaults
+                },
+                transform: Transform::from_translation(Vec3::new(xpos, ypos, 1.0)),
+                ..Default::default() // Sprite bundle defaults
+            })
+            .insert(Heap)
+            .insert(MatrixPosition { x, y })
+            .insert(block);
+    }
+}
+
+/// Create the ghost block entities, at the bottom of the field below the current tetromino
+fn spawn_ghost(commands: &mut Commands, config: &GameConfig, matrix: &Matrix) {
+    let (tet_type, blocks) = match (matrix.game.piece(), matrix.game.ghost()) {
+        (Some(piece), Some(blocks)) => (piece.tetromino_type, blocks),
+        _ => return,
+    };
+    let color = Tetromino::COLORS[tet_type as usize];
+    for &(x, y) in blocks.iter() {
+        let (xpos, ypos) = grid_position(config, matrix, x, y);
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    custom_size: Some(Vec2::new(config.block_size, config.block_size)),
+                    color: Color::rgba(color.0, color.1, color.2, config.ghost_alpha),
+                    ..Default::default() // Sprite defaults
+                },
+                transform: Transform::from_translation(Vec3::new(xpos, ypos, 0.5)), // Under the current tetromino if they overlap
+                ..Default::default() // Sprite bundle defaults
+            })
+            .insert(GhostBlock)
+            .insert(MatrixPosition { x, y });
     }
 }
 
@@ -764,442 +1224,945 @@ fn spawn_current_tetromino(
 #[allow(clippy::too_many_arguments)] // Lots of arguments here, some could be into tuples to make clippy happy
 fn move_current_tetromino(
     mut commands: Commands,
+    config: Res<GameConfig>,
     time: Res<Time>,                            // game time
     mut soft_drop_timer: ResMut<SoftDropTimer>, // the automatic drop timer
-    keyboard_input: Res<Input<KeyCode>>,
+    mut held_keys: ResMut<HeldKeys>,            // the keys that repeat when held
+    mut controls: Controls,                     // which keys are down, and what they do
+    mut state: ResMut<State<AppState>>,         // to pause, restart or end the game
     mut matrix: ResMut<Matrix>, // the shared game state
-    mut current_query: Query<(
-        Entity,
-        &mut MatrixPosition,
-        &TetrominoType,
-        &CurrentTetromino,
-    )>, // our current 'dropping' tetromino
+    mut current_query: Query<(Entity, &mut MatrixPosition, &CurrentTetromino)>, // our current 'dropping' tetromino
     heap_query: Query<(
         Entity,
-        &mut MatrixPosition,
+        &MatrixPosition,
         &Heap,
         Without<CurrentTetromino>,
     )>, // all the blocks in the heap, must exclude CurrentTetromino or we get a query conflict. Only used in dbug builds
-    mut text_query: Query<(&mut Text, &TextType)>, // to update the status message Paused/Game over
+    mut ghost_query: Query<(Entity, &mut MatrixPosition), GhostOnly>, // where the current tetromino will land
+    mut text_query: Query<(&mut Text, &TextType)>, // to update the score
+    mut game_events: EventWriter<GameEvent>,       // to pass on what happened in the game
     mut exit: EventWriter<AppExit>,                // to send AppExit events
+    mut recording: Option<ResMut<Recording>>,      // where the inputs go, if we are recording
+    mut playback: Option<ResMut<Playback>>,        // where the inputs come from, if we are playing a replay
 ) {
     // Tick
     soft_drop_timer
         .0
         .tick(Duration::from_secs_f32(time.delta_seconds()));
 
-    // Find out what we want to do, check if we can, then do it if possible
-    let mut desired_x = 0;
-    let mut desired_y = 0;
-    let mut desired_rot = 0;
+    // Find out what we want to do, the game will check if we can
+    // Rotation first, then sideways, then down
+    let mut actions = Vec::new();
+    let pressed = |control| controls.pressed(control);
+    let just_pressed = |control| controls.just_pressed(control);
 
-    // Move left
-    if keyboard_input.just_pressed(KeyCode::J) || keyboard_input.just_pressed(KeyCode::Left) {
-        desired_x = -1;
+    // Rotate clockwise
+    if just_pressed(Control::Play(Action::RotateClockwise)) {
+        actions.push(Action::RotateClockwise);
     }
 
-    // Move right
-    if keyboard_input.just_pressed(KeyCode::L) || keyboard_input.just_pressed(KeyCode::Right) {
-        desired_x = 1;
+    // Rotate anti-clockwise
+    if just_pressed(Control::Play(Action::RotateAntiClockwise)) {
+        actions.push(Action::RotateAntiClockwise);
     }
 
-    // Down - including timed drop
-    if keyboard_input.just_pressed(KeyCode::K)
-        || keyboard_input.just_pressed(KeyCode::Down)
-        || soft_drop_timer.0.just_finished()
-    {
-        desired_y = 1;
+    // Sideways and down repeat while the key is held, on game time so the frame rate doesn't matter
+    // A slow frame can owe several moves, but there's no point trying more than the size of the grid
+    let most = matrix.game.board().width().max(matrix.game.board().height()) as usize;
+    held_keys
+        .down
+        .set_interval(soft_drop_timer.0.duration() / config.soft_drop_factor);
+
+    // Move left
+    let left = pressed(Control::Play(Action::MoveLeft));
+    for _ in 0..held_keys.left.update(left, time.delta()).min(most) {
+        actions.push(Action::MoveLeft);
     }
 
-    // Rotate clockwise
-    if keyboard_input.just_pressed(KeyCode::X) {
-        desired_rot = 1;
+    // Move right
+    let right = pressed(Control::Play(Action::MoveRight));
+    for _ in 0..held_keys.right.update(right, time.delta()).min(most) {
+        actions.push(Action::MoveRight);
     }
 
-    // Rotate anti-clockwise
-    if keyboard_input.just_pressed(KeyCode::Z) {
-        desired_rot = -1;
+    // Down
+    let down = pressed(Control::Play(Action::SoftDrop));
+    for _ in 0..held_keys.down.update(down, time.delta()).min(most) {
+        actions.push(Action::SoftDrop);
     }
 
     // Drop to bottom
-    if keyboard_input.just_pressed(KeyCode::Space) {
-        matrix.falling = true;
+    if just_pressed(Control::Play(Action::HardDrop)) {
+        actions.push(Action::HardDrop);
+    }
+
+    // Hold
+    if just_pressed(Control::Play(Action::Hold)) {
+        actions.push(Action::Hold);
     }
 
     // Testing: Print a text version of the internal occupation matrix - it should visually match the block on screen
     #[cfg(debug_assertions)]
-    if keyboard_input.just_pressed(KeyCode::Slash) {
+    if controls.keyboard_input.just_pressed(KeyCode::Slash) {
         pretty_print(&matrix);
     }
 
     // Testing: Compare the internal occupation matrix with the block positions
     #[cfg(debug_assertions)]
-    if keyboard_input.just_pressed(KeyCode::Apostrophe) {
+    if controls.keyboard_input.just_pressed(KeyCode::Apostrophe) {
         println!("Check array");
         // Build an occupation array from the entities
-        //let array_size = (matrix.width * (matrix.height + Global::START_POS.1)) as usize;
-        let mut test_field_array = Vec::with_capacity(matrix.array_size);
-        for _x in 0..matrix.array_size {
-            test_field_array.push(0);
-        }
+        let board = matrix.game.board();
+        let mut test_field_array = vec![0; board.occupation().len()];
 
         // Current blocks
-        for (_entity, position, _tet_type, _current) in current_query.iter() {
-            let address = (matrix.width * position.y + position.x) as usize;
+        for (_entity, position, _current) in current_query.iter() {
+            let address = (board.width() * position.y + position.x) as usize;
             test_field_array[address] = 1;
         }
 
         // Heap blocks
         for (_entity, position, _heap, _current) in heap_query.iter() {
-            let address = (matrix.width * position.y + position.x) as usize;
+            let address = (board.width() * position.y + position.x) as usize;
             test_field_array[address] = 2;
         }
 
         // Compare
-        for y in 0..matrix.full_height {
-            for x in 0..matrix.width {
-                let address = (matrix.width * y + x) as usize;
-                if test_field_array[address] != matrix.occupation[address] {
+        for y in 0..board.height() {
+            for x in 0..board.width() {
+                let address = (board.width() * y + x) as usize;
+                if test_field_array[address] != board.occupation()[address] {
                     println!(
                         "Array mismatch @({},{}) test:{} vs matrix:{}",
-                        x, y, test_field_array[address], matrix.occupation[address]
+                        x, y, test_field_array[address], board.occupation()[address]
                     );
                 }
             }
         }
     }
+    #[cfg(not(debug_assertions))]
+    let _ = heap_query;
 
     // Quit
-    if keyboard_input.just_pressed(KeyCode::Q) {
+    if just_pressed(Control::Quit) {
+        if let Some(recording) = &recording {
+            save_recording(recording);
+        }
         exit.send(AppExit);
     }
 
-    // Pause / unpause
-    #[allow(clippy::collapsible_if)] // the !matrix.game_over check can be folded into the keycode line, but it seems cleaner to keep all the keycode checks the same
-    if keyboard_input.just_pressed(KeyCode::P) || keyboard_input.just_pressed(KeyCode::Escape) {
-        if !matrix.game_over {
-            matrix.active = !matrix.active;
-            soft_drop_timer.0.reset();
-
-            for (mut text, text_type) in text_query.iter_mut() {
-                if text_type.id == TextTypes::Status {
-                    if matrix.active {
-                        text.sections[0].value = "".to_string();
-                    } else {
-                        text.sections[0].value = "Paused".to_string();
-                    }
-                }
-            }
-        }
-    }
+    // Any of these changes the state, and the game stops here for this frame
+    // (errors only mean another change is already on its way, which can have the frame)
+    let mut leaving = false;
 
-    // Restart
-    if keyboard_input.just_pressed(KeyCode::R) {
-        let restart = Restart;
-        commands.insert_resource(restart);
+    // Pause, the pause menu goes on top of the game (and Pause again comes back to it)
+    if just_pressed(Control::Pause) && state.push(AppState::Paused).is_ok() {
+        leaving = true;
     }
 
-    // If the block is falling, that's all we allow, so no steering a falling block
-    if matrix.falling {
-        desired_y = 1;
-        desired_x = 0;
-        desired_rot = 0;
-        matrix.drop_rows += 1;
+    // Show / hide the ghost
+    if just_pressed(Control::Ghost) {
+        matrix.show_ghost = !matrix.show_ghost;
+        if matrix.show_ghost {
+            spawn_ghost(&mut commands, &config, &matrix);
+        } else {
+            for (entity, _position) in ghost_query.iter() {
+                commands.entity(entity).despawn();
+            }
+        }
     }
 
-    // If we are paused, don't do anything else
-    if !matrix.active {
-        return;
+    // Restart, leaving Playing and coming straight back in starts a new game
+    if just_pressed(Control::Restart) && state.restart().is_ok() {
+        leaving = true;
     }
 
-    // If we don't want to move, don't waste time checking
-    if desired_x == 0 && desired_y == 0 && desired_rot == 0 {
-        return;
+    // Open the settings (the key bindings), the game waits until they close
+    if just_pressed(Control::Bindings) && state.push(AppState::Settings).is_ok() {
+        leaving = true;
     }
 
-    // Rotation check
-    let mut can_rot = true;
-    if desired_rot != 0 {
-        let mut max_x = 0;
-        let mut min_x = matrix.width;
-        let mut max_y = 0;
-        let mut min_y = matrix.full_height;
-
-        // Find the bounding box of the current entity
-        for (_entity, position, _tet_type, _current) in current_query.iter_mut() {
-            if position.x < min_x {
-                min_x = position.x;
+    // Don't move anything if we are on our way out - but still pass on what has happened (eg the first tetromino)
+    if leaving {
+        controls.consume();
+        soft_drop_timer.0.reset();
+    } else {
+        // Everything the game gets this frame, either from the keyboard and timer or from a replay
+        let frame = match &mut playback {
+            Some(playback) => playback.0.next_frame(),
+            None => {
+                let mut inputs: Vec<ReplayInput> = actions.into_iter().map(ReplayInput::Action).collect();
+                // A Master has its own gravity (in `Game::step`), the timer is only for the soft drop there
+                if soft_drop_timer.0.just_finished() && !matrix.game.falls_by_itself() {
+                    inputs.push(ReplayInput::Gravity);
+                }
+                Some(Frame {
+                    elapsed: Replay::frame_time(time.delta()), // rounded the same as in a replay file
+                    inputs,
+                })
             }
-            if position.x > max_x {
-                max_x = position.x;
+        };
+
+        match frame {
+            Some(frame) => {
+                for &input in frame.inputs.iter() {
+                    if let Some(recording) = &mut recording {
+                        recording.replay.record(input);
+                    }
+                    input.apply_to(&mut matrix.game);
+                }
+                matrix.game.step(frame.elapsed); // The lock delay runs on game time
+                if let Some(recording) = &mut recording {
+                    recording.replay.next_frame(frame.elapsed);
+                }
             }
-            if position.y < min_y {
-                min_y = position.y;
-            }
-            if position.y > max_y {
-                max_y = position.y;
+            None => {
+                // The replay has run out, so stop where it stopped
+                let _ = state.overwrite_set(AppState::GameOver);
             }
         }
+    }
+
+    // Now make the blocks follow the game. Only the tetromino that was there at the start of the frame has entities
+    // (spawn_current_tetromino makes them after this), any others that come and go in the same frame (eg with no
+    // lock delay, or a hold straight after a lock) don't
+    let mut has_entities = !current_query.is_empty();
+    let mut spawned = None; // the latest tetromino to appear this frame
+    let mut moved = false;
+    for event in matrix.game.take_events() {
+        match event {
+            GameEvent::Moved(blocks) => {
+                if has_entities {
+                    move_blocks(&mut commands, &mut current_query, &blocks);
+                    moved = true;
+                }
+            }
+            GameEvent::Held(tet_type) => {
+                // The held tetromino is drawn in the hold panel instead
+                if has_entities {
+                    for (entity, _position, _current) in current_query.iter() {
+                        commands.entity(entity).despawn();
+                    }
+                    for (entity, _position) in ghost_query.iter() {
+                        commands.entity(entity).despawn();
+                    }
+                }
+                has_entities = false;
+                game_events.send(GameEvent::Held(tet_type));
+            }
+            GameEvent::Locked(blocks) => {
+                if has_entities {
+                    // Move the blocks to where they landed and put them on the heap
+                    debug_assert_eq!(current_query.iter().count(), 4, "the current tetromino's blocks");
+                    move_blocks(&mut commands, &mut current_query, &blocks);
+                    for (entity, _position, _current) in current_query.iter() {
+                        commands.entity(entity).remove::<CurrentTetromino>(); // Remove the component that triggers processing
+                        commands.entity(entity).insert(Heap); // Put it on the heap
+                    }
+                    // The next tetromino gets a new ghost when it is spawned
+                    for (entity, _position) in ghost_query.iter() {
+                        commands.entity(entity).despawn();
+                    }
+                } else if let Some(tet_type) = spawned {
+                    // A tetromino that appeared and locked within the frame goes straight onto the heap
+                    spawn_heap_blocks(&mut commands, &config, &matrix, tet_type, &blocks);
+                }
+                has_entities = false;
+            }
+            GameEvent::Spawned(tet_type, blocks) => {
+                spawned = Some(tet_type);
+                game_events.send(GameEvent::Spawned(tet_type, blocks));
+            }
+            GameEvent::GameOver | GameEvent::Finished => {
+                // Whatever else was asked for this frame, the game is over (or done). Reaching the top only ends
+                // the game if the mode says so (Mode::top_out), a Zen goes on with ToppedOut instead
+                let _ = state.overwrite_set(AppState::GameOver);
 
-        // Size of the bounds, which give us one of seven shapes
-        // These shapes are not the tetrominos themselves, but the shape of the bounding box.
-        let size_x = 1 + max_x - min_x;
-        let size_y = 1 + max_y - min_y;
-        let mut scan_min_x = 0; // Area we have to scan for collisions
-        let mut scan_max_x = 0;
-        let mut scan_min_y = 0;
-        let mut scan_max_y = 0;
-        //#[rustfmt::skip] // Much easier to read with horizontal formatting
-        match (size_x, size_y, desired_rot) {
-            (2, 2, _) => { can_rot = false; } // Square, do nothing
+                if let Some(recording) = &recording {
+                    save_recording(recording);
+                }
+            }
+            // Entities for new tetrominoes and removing rows has to wait for the heap changes to be applied
+            event => game_events.send(event),
+        }
+    }
 
-            (1, 4, 1) =>  { scan_min_x = min_x - 1; scan_max_x = max_x + 2; scan_min_y = min_y + 1; scan_max_y = min_y + 1; } //Vbar - rotate +
-            (1, 4, -1) => { scan_min_x = min_x - 2; scan_max_x = max_x + 1; scan_min_y = min_y + 1; scan_max_y = min_y + 1; } //Vbar - rotate -
+    // Dropping scores as it goes, so don't wait for the next tetromino to show it
+    let score = format!(" {:07}", matrix.game.score());
+    for (mut text, text_type) in text_query.iter_mut() {
+        if text_type.id == TextTypes::Score && text.sections[1].value != score {
+            text.sections[1].value = score.clone();
+        }
+    }
 
-            (4, 1, 1) =>  { scan_min_x = min_x + 1; scan_max_x = min_x + 1; scan_min_y = min_y + 1; scan_max_y = max_y + 2; } //Hbar - rotate +
-            (4, 1, -1) => { scan_min_x = min_x - 1; scan_max_x = min_x - 1; scan_min_y = min_y + 1; scan_max_y = max_y + 2; } //Hbar - rotate -
+    // The ghost follows the current tetromino, to where it is now
+    if moved && has_entities {
+        if let Some(blocks) = matrix.game.ghost() {
+            for ((entity, mut position), &(x, y)) in ghost_query.iter_mut().zip(blocks.iter()) {
+                position.x = x;
+                position.y = y;
+                commands.entity(entity).insert(UpdateBlock);
+            }
+        }
+    }
+}
 
-            (2, 3, 1) =>  { scan_min_x = min_x - 1; scan_max_x = min_x + 1; scan_min_y = min_y;     scan_max_y = min_y + 1; } //Vrect - rotate +
-            (2, 3, -1) => { scan_min_x = min_x;     scan_max_x = min_x + 2; scan_min_y = min_y;     scan_max_y = min_y + 1; } //Vrect - rotate -
+/// Move the current tetromino's block entities to the given grid positions
+fn move_blocks(
+    commands: &mut Commands,
+    current_query: &mut Query<(Entity, &mut MatrixPosition, &CurrentTetromino)>,
+    blocks: &[(i32, i32); 4],
+) {
+    // The blocks are all the same colour so it doesn't matter which entity goes where
+    for ((entity, mut position, _current), &(x, y)) in current_query.iter_mut().zip(blocks.iter()) {
+        position.x = x;
+        position.y = y;
+        commands.entity(entity).insert(UpdateBlock);
+    }
+}
 
-            (3, 2, 1) =>  { scan_min_x = min_x + 1; scan_max_x = min_x + 2; scan_min_y = max_y + 1; scan_max_y = max_y + 1; } //Hrect - rotate +
-            (3, 2, -1) => { scan_min_x = min_x;     scan_max_x = min_x + 1; scan_min_y = max_y + 1; scan_max_y = max_y + 1; } //Hrect - rotate -
+/// Reposition the Updated block sprites based on their grid position
+fn update_block_sprites(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    matrix: Res<Matrix>,
+    mut block_query: Query<(Entity, &MatrixPosition, &mut Transform, &UpdateBlock)>,
+) {
+    // Move to the new position
+    for (entity, position, mut transform, _) in block_query.iter_mut() {
+        let (xpos, ypos) = grid_position(&config, &matrix, position.x, position.y);
+        commands.entity(entity).remove::<UpdateBlock>();
+        let translation = &mut transform.translation;
+        translation.x = xpos;
+        translation.y = ypos;
+    }
+}
 
-            (_x, _y, _r) => {} //Unknown
+/// Remove the kind of clear from under the score (and any banner) once it has been there long enough
+fn end_announcement(
+    time: Res<Time>,
+    mut announcement: ResMut<Announcement>,
+    mut text_query: Query<(&mut Text, &TextType)>,
+) {
+    if announcement.timer.tick(time.delta()).just_finished() {
+        announcement.text.clear();
+        announcement.banner.clear();
+        for (mut text, text_type) in text_query.iter_mut() {
+            if text_type.id == TextTypes::Clear || text_type.id == TextTypes::Banner {
+                text.sections[0].value.clear();
+            }
         }
+    }
+}
 
-        // Are we trying to rotate over the border?
-        if scan_min_x < 0
-            || scan_max_x >= matrix.width
-            || scan_min_y < 0
-            || scan_max_y >= matrix.full_height
-        {
-            #[cfg(debug_assertions)]
-            println!(
-                "Rotation Border Collision {:?},{:?} - {:?},{:?} ",
-                scan_min_x, scan_min_y, scan_max_x, scan_max_y
-            );
-            can_rot = false;
+/// Keep what the mode has to say up to date: the time of a race against the clock (a Sprint), the time left of a
+/// game against it (an Ultra) and the gravity (a Zen)
+fn show_mode_status(matrix: Res<Matrix>, rival: Res<Rival>, mut text_query: Query<(&mut Text, &TextType)>) {
+    let timer = timer_text(&matrix.game, &rival.0);
+    let countdown = countdown_text(&matrix.game);
+    let gravity = gravity_text(&matrix);
+    for (mut text, text_type) in text_query.iter_mut() {
+        let value = match text_type.id {
+            TextTypes::Time => &timer,
+            TextTypes::Countdown => &countdown,
+            TextTypes::Gravity => &gravity,
+            _ => continue,
+        };
+        if text.sections[0].value != *value {
+            text.sections[0].value = value.clone();
         }
+    }
+}
 
-        // Check the matrix for any heap collisions if we are still ok
-        if can_rot {
-            'row_scan: for x in scan_min_x..=scan_max_x {
-                for y in scan_min_y..=scan_max_y {
-                    let address = (matrix.width * y + x) as usize;
-                    if matrix.occupation[address] == 1
-                        && (x < min_x || x > max_x || y < min_y || y > max_y)
-                    {
-                        can_rot = false;
-                        break 'row_scan;
-                    };
-                }
-            }
+/// Turn the gravity up and down while the game goes on, if the mode lets us choose it (a Zen)
+fn adjust_gravity(
+    controls: Controls,
+    config: Res<GameConfig>,
+    mut matrix: ResMut<Matrix>,
+    mut soft_drop_timer: ResMut<SoftDropTimer>,
+) {
+    let level = match matrix.gravity {
+        Some(level) => level,
+        None => return,
+    };
+    let level = if controls.just_pressed(Control::GravityUp) {
+        (level + 1).min(config.max_level)
+    } else if controls.just_pressed(Control::GravityDown) {
+        level.saturating_sub(1).max(1)
+    } else {
+        return;
+    };
+    // The next drop comes at the new speed, the ones after it too
+    matrix.gravity = Some(level);
+    soft_drop_timer.0.set_duration(Duration::from_secs_f32(drop_interval(&config, &matrix)));
+}
+
+/// The combo and back-to-back streaks that are going, one per line, or nothing if neither is
+fn streak_text(game: &Game) -> String {
+    let mut lines = Vec::new();
+    if let Some(combo) = game.combo().filter(|&combo| combo > 0) {
+        lines.push(format!("Combo {}", combo));
+    }
+    if game.back_to_back() {
+        lines.push("Back-to-Back".to_string());
+    }
+    lines.join("\n")
+}
+
+/// Fade the current tetromino as its lock delay runs out, so you can see how long you have left to slide it
+fn show_lock_delay(
+    config: Res<GameConfig>,
+    matrix: Res<Matrix>,
+    mut current_query: Query<(&mut Sprite, &Block), With<CurrentTetromino>>,
+) {
+    let progress = matrix.game.lock_progress().unwrap_or(0.0);
+    let alpha = 1.0 - progress * (1.0 - config.lock_alpha);
+    for (mut sprite, block) in current_query.iter_mut() {
+        if sprite.color.a() != alpha {
+            sprite.color = *block.color.clone().set_a(alpha);
         }
+    }
+}
 
-        // Do the rotation
-        if can_rot {
-            //// Clear the current grid prositions
-            //for x in min_x..=max_x {
-            //    for y in min_y..=max_y {
-            //        let address = (matrix.width * y + x) as usize;
-            //        matrix.occupation[address] = 0;
-            //    }
-            //}
-
-            // Move the blocks
-            for (entity, mut position, _tet_type, _current) in current_query.iter_mut() {
-                // Clear the current position
-                let address = (matrix.width * position.y + position.x) as usize;
-                matrix.occupation[address] = 0;
-                let (x, y) = rotate_block(
-                    position.x,
-                    position.y,
-                    min_x,
-                    min_y,
-                    size_x,
-                    size_y,
-                    desired_rot,
-                );
-                position.x = x;
-                position.y = y;
-                // Set the new position
-                let address = (matrix.width * position.y + position.x) as usize;
-                matrix.occupation[address] = 1;
-                commands.entity(entity).insert(UpdateBlock);
-            }
+/// Put up the menu for the state we are in (the main menu, the pause menu or the game over menu), under the status
+fn open_menu(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    asset_server: Res<AssetServer>,
+    state: Res<State<AppState>>,
+    matrix: Res<Matrix>,
+    playback: Option<Res<Playback>>,
+    mut text_query: Query<(&mut Text, &TextType)>,
+) {
+    let menu = Menu {
+        heading: "".to_string(),
+        items: state.current().menu(),
+        selected: 0,
+    };
 
-            // If we successfully rotate, don't allow horizontal/vertical movement in the same frame, as it seems to confuse the entity query
-            //desired_x = 0;
-            //desired_y = 0;
+    // The menu sits in a panel near the bottom of the window, so the status ('Paused', 'Game over' etc) stays in view
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            color: UiColor(Color::NONE),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        position: Rect {
+                            bottom: Val::Percent(8.0),
+                            ..Default::default()
+                        },
+                        padding: Rect::all(Val::Px(config.block_size)),
+                        ..Default::default()
+                    },
+                    color: UiColor(Color::rgba(0.0, 0.0, 0.0, 0.85)),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    // The heading (smaller), then the choices
+                    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+                    parent
+                        .spawn_bundle(TextBundle {
+                            text: Text {
+                                sections: vec![
+                                    TextSection {
+                                        value: menu.heading.clone(),
+                                        style: TextStyle {
+                                            font: font.clone(),
+                                            font_size: config.score_size.1 * 0.8,
+                                            color: Color::from(config.scorelabel_color),
+                                        },
+                                    },
+                                    TextSection {
+                                        value: menu_text(&menu),
+                                        style: TextStyle {
+                                            font,
+                                            font_size: config.score_size.1,
+                                            color: Color::from(config.scorelabel_color),
+                                        },
+                                    },
+                                ],
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        })
+                        .insert(MenuText);
+                });
+        })
+        .insert(menu);
+
+    let (status, detail) = status_text(*state.current(), &matrix, playback.as_deref());
+    for (mut text, text_type) in text_query.iter_mut() {
+        if text_type.id == TextTypes::Status {
+            text.sections[0].value = status.clone();
+            text.sections[1].value = detail.clone();
         }
     }
+}
 
-    // Horizontal/Vertical movement check
-    let mut can_move_x = true; //
-    let mut can_move_y = true; // We don't really expect to get an x AND y move in the same frame, but best to be sure.
-                               // Scan the heap for collisions - if we want to move vertically or horizontally
-    if (desired_x + desired_y) != 0 {
-        'my_piece: for (_entity, position, _tet_type, _current) in current_query.iter_mut() {
-            // Sidewalls?
-            if position.x + desired_x < 0 || position.x + desired_x > matrix.width - 1 {
-                can_move_x = false;
-            }
+/// Take the menu down when we leave its state, or another state goes on top of it
+fn close_menu(mut commands: Commands, menu_query: Query<Entity, With<Menu>>) {
+    for entity in menu_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
 
-            // Bottom?
-            if position.y + desired_y > matrix.max_ypos {
-                can_move_y = false;
-                break 'my_piece; // Can't do anything if we've hit the bottom
-            }
+/// Choose from the menu with Up/Down and Return. Pause, Restart and Quit work here too, where the menu has them
+#[allow(clippy::too_many_arguments)] // Lots of arguments here, a menu can lead anywhere
+fn choose_from_menu(
+    mut controls: Controls,
+    mut state: ResMut<State<AppState>>,
+    mut menu_query: Query<&mut Menu>,
+    mut text_query: Query<&mut Text, With<MenuText>>,
+    mut exit: EventWriter<AppExit>,
+    recording: Option<Res<Recording>>,
+    new_score: Option<Res<NewScore>>,
+    mut game_mode: ResMut<GameMode>,
+) {
+    // The menu is spawned with commands, so it isn't there for the first frame of its state
+    let mut menu = match menu_query.get_single_mut() {
+        Ok(menu) => menu,
+        Err(_) => return,
+    };
+    let has = |menu: &Menu, item: MenuItem| menu.items.iter().any(|&(_label, other)| other == item);
 
-            // occupation grid scan - only collide with heap blocks
-            if can_move_y && desired_y != 0 {
-                let address = (matrix.width * (position.y + desired_y) + (position.x)) as usize;
-                if matrix.occupation[address] == 2 {
-                    can_move_y = false;
-                }
-            }
+    // The keys are for typing while a name goes in for a new high score
+    let typing = matches!(&new_score, Some(new_score) if new_score.typing);
+
+    let count = menu.items.len();
+    let mut chosen = match controls.menu_input().filter(|_input| !typing) {
+        Some(MenuInput::Up) => {
+            menu.selected = (menu.selected + count - 1) % count;
+            None
+        }
+        Some(MenuInput::Down) => {
+            menu.selected = (menu.selected + 1) % count;
+            None
+        }
+        Some(MenuInput::Choose) => Some(menu.items[menu.selected].1),
+        Some(MenuInput::Back) => Some(MenuItem::Resume).filter(|&item| has(&menu, item)), // only a paused game goes back
+        Some(MenuInput::Left | MenuInput::Right) | None => None,
+    };
+    if !typing && controls.just_pressed(Control::Pause) && has(&menu, MenuItem::Resume) {
+        chosen = Some(MenuItem::Resume);
+    }
+    if !typing && controls.just_pressed(Control::Restart) && has(&menu, MenuItem::NewGame) {
+        chosen = Some(MenuItem::NewGame);
+    }
+    if !typing && controls.just_pressed(Control::Quit) && has(&menu, MenuItem::Quit) {
+        chosen = Some(MenuItem::Quit);
+    }
 
-            if can_move_x && desired_x != 0 {
-                let address = (matrix.width * (position.y) + (position.x + desired_x)) as usize;
-                if matrix.occupation[address] == 2 {
-                    can_move_x = false;
+    // Errors only mean another change is already on its way, which can have the frame
+    if let Some(item) = chosen {
+        let _ = match item {
+            MenuItem::Play(mode) => {
+                game_mode.0 = mode;
+                state.overwrite_replace(AppState::Playing)
+            }
+            MenuItem::NewGame => state.overwrite_replace(AppState::Playing), // from underneath any other state
+            MenuItem::Resume => state.overwrite_pop(),
+            MenuItem::HighScores => state.overwrite_push(AppState::HighScores),
+            MenuItem::Settings => state.overwrite_push(AppState::Settings),
+            MenuItem::MainMenu => state.overwrite_replace(AppState::MainMenu),
+            MenuItem::Quit => {
+                if let Some(recording) = &recording {
+                    save_recording(recording);
                 }
+                exit.send(AppExit);
+                Ok(())
             }
+        };
+        controls.consume();
+    }
 
-            // If we (now) can't move, stop checking
-            if !can_move_x && !can_move_y {
-                break 'my_piece;
-            }
+    let value = menu_text(&menu);
+    for mut text in text_query.iter_mut() {
+        if text.sections[0].value != menu.heading {
+            text.sections[0].value = menu.heading.clone();
         }
+        if text.sections[1].value != value {
+            text.sections[1].value = value.clone();
+        }
+    }
+}
+
+/// At the end of a game, see whether it has made its mode's high score table.
+/// A replay is somebody else's game, and an unfinished Sprint has no time, so they don't
+fn check_high_score(
+    mut commands: Commands,
+    matrix: Res<Matrix>,
+    score_tables: Res<ScoreTables>,
+    playback: Option<Res<Playback>>,
+) {
+    commands.remove_resource::<NewScore>(); // from the last game
+    if playback.is_some() {
+        return;
+    }
+    let mode = matrix.game.settings().mode;
+    let entry = match Entry::for_game(&matrix.game, scores::date(SystemTime::now())) {
+        Some(entry) => entry,
+        None => return,
+    };
+    if let Some(rank) = score_tables.get(mode).scores.rank(&entry) {
+        commands.insert_resource(NewScore {
+            mode,
+            entry,
+            rank,
+            typing: true,
+            ready: false,
+        });
+    }
+}
 
-        // If we can move, do so
-        if can_move_x || can_move_y {
-            for (_entity, position, _tet_type, _current) in current_query.iter_mut() {
-                let address = (matrix.width * position.y + position.x) as usize;
-                matrix.occupation[address] = 0;
+/// Type in a name for a new high score: Backspace rubs out, Return (or South on a gamepad) puts it in the table
+/// and saves it. Above the game over menu, show the name being typed, then the top of the table
+fn enter_name(
+    mut controls: Controls,
+    mut characters: EventReader<ReceivedCharacter>,
+    matrix: Res<Matrix>,
+    mut new_score: Option<ResMut<NewScore>>,
+    mut score_tables: ResMut<ScoreTables>,
+    mut menu_query: Query<&mut Menu>,
+) {
+    if let Some(new_score) = new_score.as_mut().filter(|new_score| new_score.typing) {
+        if !new_score.ready {
+            // Anything still waiting to be read was typed during the game
+            for _character in characters.iter() {}
+            new_score.ready = true;
+        } else {
+            for character in characters.iter() {
+                let name = &mut new_score.entry.name;
+                let allowed = character.char.is_alphanumeric() || " -_.'".contains(character.char);
+                if allowed && name.chars().count() < NAME_LENGTH && !(name.is_empty() && character.char == ' ') {
+                    name.push(character.char);
+                }
+            }
+            if controls.keyboard_input.just_pressed(KeyCode::Back) {
+                new_score.entry.name.pop();
             }
-            for (entity, mut position, _tet_type, _current) in current_query.iter_mut() {
-                if can_move_x {
-                    position.x += desired_x;
+
+            let keys = [KeyCode::Return, KeyCode::NumpadEnter, KeyCode::Escape];
+            if keys.iter().any(|&key| controls.keyboard_input.just_pressed(key))
+                || controls.any_button(&[GamepadButtonType::South, GamepadButtonType::Start])
+            {
+                let name = new_score.entry.name.trim().to_string();
+                new_score.entry.name = if name.is_empty() { "Player".to_string() } else { name };
+                new_score.typing = false;
+                let score_table = score_tables.get_mut(new_score.mode);
+                if let Some(rank) = score_table.scores.insert(new_score.entry.clone()) {
+                    new_score.rank = rank;
                 }
-                if can_move_y {
-                    position.y += desired_y;
+                if let Err(error) = score_table.scores.save(&score_table.path) {
+                    eprintln!("Can't save the high scores to {}: {}", score_table.path.display(), error);
                 }
-                let address = (matrix.width * position.y + position.x) as usize;
-                matrix.occupation[address] = 1;
-                commands.entity(entity).insert(UpdateBlock);
+                controls.consume(); // the menu comes next, and mustn't take this as a choice
             }
         }
+    }
 
-        // If we want to move down but can't, we must have landed on something, so move this block to the heap and get the next one
-        if !can_move_y && desired_y != 0 {
-            // If any block is still in the top buffer, we have lost
-            for (entity, position, _tet_type, _current) in current_query.iter_mut() {
-                if position.y < 4 {
-                    matrix.game_over = true;
-                    matrix.active = false;
-                    //todo: something better?
-
-                    for (mut text, text_type) in text_query.iter_mut() {
-                        if text_type.id == TextTypes::Status {
-                            text.sections[0].value = "Game over".to_string();
-                        }
-                    }
-                }
-                commands.entity(entity).remove::<CurrentTetromino>(); // Remove the component that triggers processing
-                commands.entity(entity).insert(Heap); // Put it on the heap
-                let address = (matrix.width * position.y + position.x) as usize;
-                matrix.occupation[address] = 2;
-            }
+    let scores = &score_tables.get(matrix.game.settings().mode).scores;
+    let heading = game_over_heading(scores, new_score.as_deref());
+    for mut menu in menu_query.iter_mut() {
+        if menu.heading != heading {
+            menu.heading = heading.clone();
+        }
+    }
+}
 
-            // If we were falling, adjust the score
-            if matrix.falling {
-                matrix.score += matrix.drop_rows - 1; // -1 because we increment this counter before checking for collisions
-            }
+/// The settings screen: the key bindings, over whatever it was opened from
+fn open_settings(mut commands: Commands, config: Res<GameConfig>, asset_server: Res<AssetServer>) {
+    commands.insert_resource(Rebinding::default());
+    spawn_screen(&mut commands, &config, &asset_server, ""); // the bindings fill it in
+}
 
-            // If we haven't lost, trigger the next tetromino
-            if !matrix.game_over {
-                matrix.create = true;
+/// The bindings on the settings screen: choose a control with Up/Down, Return adds a key or gamepad button to it,
+/// Backspace takes its keys and buttons off, Escape closes the screen (or stops waiting for a key) and saves any changes
+fn rebind_keys(
+    mut controls: Controls,
+    mut key_bindings: ResMut<KeyBindings>,
+    mut rebinding: ResMut<Rebinding>,
+    mut state: ResMut<State<AppState>>,
+    mut text_query: Query<&mut Text, With<ScreenText>>,
+) {
+    let keyboard_input = &controls.keyboard_input;
+    let control = Control::ALL[rebinding.selected];
+    if rebinding.waiting {
+        // The next key or button goes on the selected control, unless it already does something else
+        // Escape stops waiting, so it must never get as far as being bound
+        let cancel = keyboard_input.just_pressed(KeyCode::Escape);
+        let key = keyboard_input.get_just_pressed().find(|key| KEYS.contains(key));
+        let button = controls.button_input.get_just_pressed().find(|button| BUTTONS.contains(&button.1));
+        let bound = if cancel {
+            None
+        } else if let Some(&key) = key {
+            let name = key_name(key);
+            Some((key_bindings.bindings.bind(control, &name), name))
+        } else if let Some(&GamepadButton(_gamepad, button_type)) = button {
+            let name = button_name(button_type);
+            Some((key_bindings.bindings.bind_button(control, &name), name))
+        } else {
+            None
+        };
+        if cancel {
+            rebinding.waiting = false;
+            rebinding.message.clear();
+        } else if let Some((result, name)) = bound {
+            match result {
+                Ok(()) => {
+                    rebinding.waiting = false;
+                    rebinding.changed = true;
+                    rebinding.message.clear();
+                    key_bindings.update_codes();
+                }
+                Err(other) => {
+                    rebinding.message = format!("{} is already {}, try another key (Escape cancels)", name, other.name());
+                }
             }
         }
+    } else if keyboard_input.just_pressed(KeyCode::Up) {
+        rebinding.selected = (rebinding.selected + Control::ALL.len() - 1) % Control::ALL.len();
+        rebinding.message.clear();
+    } else if keyboard_input.just_pressed(KeyCode::Down) {
+        rebinding.selected = (rebinding.selected + 1) % Control::ALL.len();
+        rebinding.message.clear();
+    } else if keyboard_input.just_pressed(KeyCode::Return) {
+        rebinding.waiting = true;
+        rebinding.message = format!("Press a key or gamepad button for {} (Escape cancels)", control.name());
+    } else if keyboard_input.just_pressed(KeyCode::Back) || keyboard_input.just_pressed(KeyCode::Delete) {
+        // Without a key for this screen, there would be no way back to it
+        if control == Control::Bindings {
+            rebinding.message = "Bindings needs a key, add another one first".to_string();
+        } else {
+            key_bindings.bindings.clear(control);
+            key_bindings.update_codes();
+            rebinding.changed = true;
+        }
+    } else if keyboard_input.just_pressed(KeyCode::Escape) {
+        if key_bindings.bindings.keys(Control::Bindings).is_empty() {
+            rebinding.message = "Bindings needs a key".to_string();
+            return;
+        }
+        if rebinding.changed {
+            if let Err(error) = key_bindings.bindings.save(&key_bindings.path) {
+                eprintln!("Can't save the key bindings to {}: {}", key_bindings.path.display(), error);
+            }
+        }
+        // Back to wherever we came from
+        let _ = state.overwrite_pop();
+        controls.consume();
+        return;
+    }
+
+    // Show every control with its keys and then its buttons, the selected one marked
+    let mut lines = vec!["Settings: key bindings\n".to_string()];
+    for (index, &control) in Control::ALL.iter().enumerate() {
+        let marker = if index == rebinding.selected { ">" } else { "  " };
+        let keys = key_bindings.bindings.keys(control).join(", ");
+        let buttons = key_bindings.bindings.buttons(control).join(", ");
+        lines.push(format!("{} {}:  {}  |  {}", marker, control.name(), keys, buttons));
+    }
+    lines.push("\nUp/Down: choose   Return: add a key   Backspace: remove them all   Escape: done".to_string());
+    lines.push(rebinding.message.clone());
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = lines.join("\n");
     }
 }
 
-/// Reposition the Updated block sprites based on their grid position
-fn update_block_sprites(
+/// The high score screen, over the menu it was opened from. It starts with the table for the mode last played
+fn open_high_scores(
     mut commands: Commands,
-    matrix: Res<Matrix>,
-    mut block_query: Query<(Entity, &MatrixPosition, &mut Transform, &UpdateBlock)>,
+    config: Res<GameConfig>,
+    asset_server: Res<AssetServer>,
+    game_mode: Res<GameMode>,
+    score_tables: Res<ScoreTables>,
 ) {
-    // Move to the new position
-    for (entity, position, mut transform, _) in block_query.iter_mut() {
-        let (xpos, ypos) = grid_position(&matrix, position.x, position.y);
-        commands.entity(entity).remove::<UpdateBlock>();
-        let translation = &mut transform.translation;
-        translation.x = xpos;
-        translation.y = ypos;
+    let mode = if game_mode.0.keeps_scores() { game_mode.0 } else { Mode::Marathon };
+    commands.insert_resource(ScoresScreen(mode));
+    let text = high_scores_text(mode, &score_tables.get(mode).scores);
+    spawn_screen(&mut commands, &config, &asset_server, &text);
+}
+
+/// Left / Right go through the tables of each mode that keeps them, Escape (or Return) goes back
+fn browse_high_scores(
+    mut controls: Controls,
+    mut state: ResMut<State<AppState>>,
+    mut screen: ResMut<ScoresScreen>,
+    score_tables: Res<ScoreTables>,
+    mut text_query: Query<&mut Text, With<ScreenText>>,
+) {
+    let modes: Vec<Mode> = Mode::ALL.iter().copied().filter(|mode| mode.keeps_scores()).collect();
+    let index = modes.iter().position(|&mode| mode == screen.0).unwrap_or_default();
+    let count = modes.len();
+    match controls.menu_input() {
+        Some(MenuInput::Back | MenuInput::Choose) => {
+            let _ = state.overwrite_pop();
+            controls.consume();
+            return;
+        }
+        Some(MenuInput::Left) => screen.0 = modes[(index + count - 1) % count],
+        Some(MenuInput::Right) => screen.0 = modes[(index + 1) % count],
+        Some(MenuInput::Up | MenuInput::Down) | None => return,
+    }
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = high_scores_text(screen.0, &score_tables.get(screen.0).scores);
+    }
+}
+
+/// Take down the screen, and anything that was only there for it
+fn close_screen(mut commands: Commands, screen_query: Query<Entity, With<Screen>>) {
+    for entity in screen_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    commands.remove_resource::<Rebinding>();
+    commands.remove_resource::<ScoresScreen>();
+}
+
+/// Gamepads can come and go while the game runs. Bevy keeps the list of connected ones (`Gamepads`) that the
+/// controls read, so all we do here is pause the game if one is unplugged in the middle of it
+fn connect_gamepads(mut gamepad_events: EventReader<GamepadEvent>, mut state: ResMut<State<AppState>>) {
+    let mut unplugged = false;
+    for GamepadEvent(gamepad, event_type) in gamepad_events.iter() {
+        match event_type {
+            GamepadEventType::Connected => info!("Gamepad {} connected", gamepad.0),
+            GamepadEventType::Disconnected => {
+                info!("Gamepad {} disconnected", gamepad.0);
+                unplugged = true;
+            }
+            _ => {}
+        }
+    }
+
+    if unplugged && *state.current() == AppState::Playing {
+        let _ = state.overwrite_push(AppState::Paused);
     }
 }
 
-/// Start a new game
-fn restart(
+/// Start a new game, each time we enter Playing
+#[allow(clippy::too_many_arguments)] // Lots of arguments here, a new game touches nearly everything
+fn start_game(
     mut commands: Commands,
-    o_matrix: Option<ResMut<Matrix>>,
-    restart: Option<Res<Restart>>,
+    mut matrix: ResMut<Matrix>,
+    mut game_events: ResMut<Events<GameEvent>>,
+    mut soft_drop_timer: ResMut<SoftDropTimer>,
     mut block_query: Query<(Entity, &MatrixPosition, &mut Transform)>,
+    panel_query: Query<Entity, PanelBlocks>,
     mut text_query: Query<(&mut Text, &TextType)>,
+    mut recording: Option<ResMut<Recording>>,
+    mut playback: Option<ResMut<Playback>>,
+    game_mode: Res<GameMode>,
+    score_tables: Res<ScoreTables>,
+    mut rival: ResMut<Rival>,
 ) {
-    if restart.is_some() && o_matrix.is_some() {
-        // Clear the restart flag
-        commands.remove_resource::<Restart>();
+    // Remove all the blocks of the last game
+    for (entity, _position, _transform) in block_query.iter_mut() {
+        commands.entity(entity).despawn_recursive();
+    }
+    for entity in panel_query.iter() {
+        commands.entity(entity).despawn();
+    }
 
-        // Remove all the blocks
-        for (entity, _position, _transform) in block_query.iter_mut() {
-            commands.entity(entity).despawn_recursive();
-        }
+    // Anything the old game had to say is no longer relevant
+    game_events.clear();
+
+    // Switch to the mode chosen in the menu, the settings are otherwise those of the last game
+    if matrix.game.settings().mode != game_mode.0 {
+        let settings = Settings {
+            mode: game_mode.0,
+            ..matrix.game.settings().clone()
+        };
+        matrix.game = Game::new(settings);
+    }
+
+    // The gravity is ours to choose in a Zen, starting from the bottom
+    matrix.gravity = if game_mode.0 == Mode::Zen { Some(1) } else { None };
+
+    // Reset the matrix
+    matrix.game.reset(); // Triggers a new tetromino and starts the game
+    soft_drop_timer.0.reset();
+
+    // Race against the personal best, as it was when the game started
+    let settings = matrix.game.settings();
+    rival.0 = score_tables
+        .get(settings.mode)
+        .scores
+        .best(&scores::mode_title(settings))
+        .map(Entry::splits)
+        .unwrap_or_default();
+
+    // A replay starts again from the beginning (its seed is in the game settings)
+    if let Some(playback) = &mut playback {
+        playback.0.rewind();
+    }
 
-        // Reset the matrix
-        #[allow(clippy::unnecessary_unwrap)] 
-        let mut matrix = o_matrix.unwrap(); // We have already determined that o_matrix is_some so this will never panic, but we still need to get at the value
-        matrix.score = 0;
-        matrix.level = 1;
-        matrix.lines_cleared = 0;
-        matrix.drop_rows = 0;
-        matrix.drop_speed = 1.0;
-        matrix.active = true;
-        matrix.falling = false;
-        matrix.create = true; // Triggers a new tetromino and starts the game
-        matrix.game_over = false;
-
-        // Clear the occupation array
-        //let array_size = (matrix.width * (matrix.height + Global::START_POS.1)) as usize;
-        for x in 0..matrix.array_size {
-            matrix.occupation[x] = 0;
+    // Keep what we have of the old game, the file holds the latest game
+    if let Some(recording) = &mut recording {
+        if !recording.replay.frames.is_empty() {
+            save_recording(recording);
         }
+        recording.replay = Replay::new(matrix.game.settings(), matrix.game.seed());
+    }
 
-        // Clear the score and status
-        for (mut text, text_type) in text_query.iter_mut() {
-            match text_type.id {
-                TextTypes::Score => {
-                    text.sections[1].value = format!(" {:07}", matrix.score);
-                }
-                TextTypes::Status => {
-                    text.sections[0].value = "".to_string();
-                }
-                _ => {}
+    // Clear the score and status
+    for (mut text, text_type) in text_query.iter_mut() {
+        match text_type.id {
+            TextTypes::Score => {
+                text.sections[1].value = format!(" {:07}", matrix.game.score());
+            }
+            TextTypes::Status => {
+                text.sections[0].value = "".to_string();
+                text.sections[1].value = "".to_string();
             }
+            TextTypes::Streak => {
+                text.sections[0].value = streak_text(&matrix.game);
+            }
+            TextTypes::Time => {
+                text.sections[0].value = timer_text(&matrix.game, &rival.0);
+            }
+            TextTypes::Countdown => {
+                text.sections[0].value = countdown_text(&matrix.game);
+            }
+            TextTypes::Gravity => {
+                text.sections[0].value = gravity_text(&matrix);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Carry on with the game, after the pause menu or the settings
+fn resume_game(mut soft_drop_timer: ResMut<SoftDropTimer>, mut text_query: Query<(&mut Text, &TextType)>) {
+    soft_drop_timer.0.reset();
+    for (mut text, text_type) in text_query.iter_mut() {
+        if text_type.id == TextTypes::Status {
+            text.sections[0].value = "".to_string();
+            text.sections[1].value = "".to_string();
         }
     }
 }
 
 /// Recreate some text UI elements when the window resizes to keep them aligned to the game field
+#[allow(clippy::too_many_arguments)] // Lots of arguments here, the text needs to know about everything it shows
 fn resize_window(
     mut commands: Commands,
+    config: Res<GameConfig>,
     mut resize_event: EventReader<WindowResized>,
     matrix: ResMut<Matrix>,
+    announcement: Res<Announcement>,
+    rival: Res<Rival>,
     asset_server: Res<AssetServer>,
     mut text_query: Query<(Entity, &mut Text, &TextType, Option<&MobileText>)>,
+    state: Res<State<AppState>>,
+    playback: Option<Res<Playback>>,
 ) {
     let mut do_recreate: bool = false;
     let mut width = 0.0;
@@ -1224,8 +2187,8 @@ fn resize_window(
         let font = asset_server.load("fonts/FiraSans-Bold.ttf");
 
         // the score label and text
-        let xpos = (width + matrix.field_width) / 2.0 + Global::SCORE_SPACE.0 * (Global::BLOCK_SIZE + Global::BLOCK_SPACE);
-        let ypos = height / 2.0 - (Global::SCORE_SPACE.1 + 1.5) * (Global::BLOCK_SIZE + Global::BLOCK_SPACE); // Note +1.5 here moves the score label UP
+        let xpos = (width + matrix.field_width) / 2.0 + config.score_space.0 * (config.block_size + config.block_space);
+        let ypos = height / 2.0 - (config.score_space.1 + 1.5) * (config.block_size + config.block_space); // Note +1.5 here moves the score label UP
         commands
             .spawn_bundle(TextBundle {
                 style: Style {
@@ -1247,26 +2210,16 @@ fn resize_window(
                             value: "Score: \n".to_string(),
                             style: TextStyle {
                                 font: font.clone(),
-                                font_size: Global::SCORE_SIZE.1,
-                                color: Color::rgba(
-                                    Global::SCORELABEL_COLOR.0,
-                                    Global::SCORELABEL_COLOR.1,
-                                    Global::SCORELABEL_COLOR.2,
-                                    Global::SCORELABEL_COLOR.3,
-                                ),
+                                font_size: config.score_size.1,
+                                color: Color::from(config.scorelabel_color),
                             },
                         },
                         TextSection {
-                            value: format!(" {:07}", matrix.score),
+                            value: format!(" {:07}", matrix.game.score()),
                             style: TextStyle {
                                 font: font.clone(),
-                                font_size: Global::SCORE_SIZE.1,
-                                color: Color::rgba(
-                                    Global::SCORE_COLOR.0,
-                                    Global::SCORE_COLOR.1,
-                                    Global::SCORE_COLOR.2,
-                                    Global::SCORE_COLOR.3,
-                                ),
+                                font_size: config.score_size.1,
+                                color: Color::from(config.score_color),
                             },
                         },
                     ],
@@ -1280,8 +2233,8 @@ fn resize_window(
             .insert(MobileText); // testing
 
         // the level label and text
-        let xpos = (width + matrix.field_width) / 2.0 + Global::SCORE_SPACE.0 * (Global::BLOCK_SIZE + Global::BLOCK_SPACE);
-        let ypos = height / 2.0 - (Global::SCORE_SPACE.1 + 3.5) * (Global::BLOCK_SIZE + Global::BLOCK_SPACE); // Note +3.5 here moves the level label UP
+        let xpos = (width + matrix.field_width) / 2.0 + config.score_space.0 * (config.block_size + config.block_space);
+        let ypos = height / 2.0 - (config.score_space.1 + 3.5) * (config.block_size + config.block_space); // Note +3.5 here moves the level label UP
         commands
             .spawn_bundle(TextBundle {
                 style: Style {
@@ -1303,26 +2256,16 @@ fn resize_window(
                             value: "Level: ".to_string(),
                             style: TextStyle {
                                 font: font.clone(),
-                                font_size: Global::SCORE_SIZE.1,
-                                color: Color::rgba(
-                                    Global::SCORELABEL_COLOR.0,
-                                    Global::SCORELABEL_COLOR.1,
-                                    Global::SCORELABEL_COLOR.2,
-                                    Global::SCORELABEL_COLOR.3,
-                                ),
+                                font_size: config.score_size.1,
+                                color: Color::from(config.scorelabel_color),
                             },
                         },
                         TextSection {
-                            value: format!(" {:02}", matrix.level),
+                            value: level_text(&matrix.game),
                             style: TextStyle {
                                 font: font.clone(),
-                                font_size: Global::SCORE_SIZE.1,
-                                color: Color::rgba(
-                                    Global::SCORE_COLOR.0,
-                                    Global::SCORE_COLOR.1,
-                                    Global::SCORE_COLOR.2,
-                                    Global::SCORE_COLOR.3,
-                                ),
+                                font_size: config.score_size.1,
+                                color: Color::from(config.score_color),
                             },
                         },
                     ],
@@ -1335,16 +2278,9 @@ fn resize_window(
             })
             .insert(MobileText); // testing
 
-        // the status label
-        //let window = windows.get_primary_mut().unwrap();
-        let xpos = (width - matrix.field_width) / 2.0;
-        let ypos = height / 2.0;
-        let mut status_text = "";
-        if matrix.game_over {
-            status_text = "Game over";
-        } else if !matrix.active {
-            status_text = "Paused";
-        }
+        // the kind of the last clear, under the score
+        let xpos = (width + matrix.field_width) / 2.0 + config.score_space.0 * (config.block_size + config.block_space);
+        let ypos = height / 2.0 - (config.score_space.1 - 0.5) * (config.block_size + config.block_space); // Note -0.5 here moves it DOWN
         commands
             .spawn_bundle(TextBundle {
                 style: Style {
@@ -1358,144 +2294,684 @@ fn resize_window(
                     },
                     ..Default::default()
                 },
-                // Use the `Text::with_section` constructor for single component elements
                 text: Text::with_section(
-                    status_text,
+                    announcement.text.clone(),
                     TextStyle {
-                        font, // the last use can consume the font, otherwise we need font.clone()
-                        font_size: Global::STATUSLABEL_SIZE,
-                        color: Color::rgba(
-                            Global::STATUSLABEL_COLOR.0,
-                            Global::STATUSLABEL_COLOR.1,
-                            Global::STATUSLABEL_COLOR.2,
-                            Global::STATUSLABEL_COLOR.3,
-                        ),
-                        //..Default::default()
+                        font: font.clone(),
+                        font_size: config.score_size.1,
+                        color: Color::from(config.score_color),
                     },
-                    TextAlignment {
-                        horizontal: HorizontalAlign::Center,
+                    Default::default(),
+                ),
+                ..Default::default()
+            })
+            .insert(TextType {
+                id: TextTypes::Clear,
+            })
+            .insert(MobileText);
+
+        // any combo or back-to-back streak that is going, under the kind of clear
+        let ypos = height / 2.0 - (config.score_space.1 - 1.5) * (config.block_size + config.block_space);
+        commands
+            .spawn_bundle(TextBundle {
+                style: Style {
+                    align_self: AlignSelf::FlexEnd,
+                    position_type: PositionType::Absolute,
+                    position: Rect {
+                        // Style positions are relative to the window top,left
+                        left: Val::Px(xpos),
+                        top: Val::Px(ypos),
                         ..Default::default()
                     },
+                    ..Default::default()
+                },
+                text: Text::with_section(
+                    streak_text(&matrix.game),
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: config.score_size.1,
+                        color: Color::from(config.scorelabel_color),
+                    },
+                    Default::default(),
                 ),
                 ..Default::default()
             })
             .insert(TextType {
-                id: TextTypes::Status,
+                id: TextTypes::Streak,
             })
             .insert(MobileText);
-    }
-}
-
-// ========================================
-// Utility functions
-
-/// Print a text version of the occupation grid. Only used in debug builds
-#[cfg(debug_assertions)]
-fn pretty_print(matrix: &Matrix) {
-    // Higher rows numbers (y) are at the bottom
-    for y in 0..matrix.full_height {
-        let slice_start = (matrix.width * y) as usize;
-        let slice_end = (matrix.width * (y + 1)) as usize; // not included in slice
-        let slice = &(matrix.occupation)[slice_start..slice_end];
-        println!("occupation {:2} {:?}", y, slice);
-    }
-}
 
-/// Calculate screen position from the block co-ordinates in the playing grid
-fn grid_position(matrix: &Matrix, xpos: i32, ypos: i32) -> (f32, f32) {
-    let x =
-        -(matrix.field_width) / 2.0 + xpos as f32 * (Global::BLOCK_SIZE + Global::BLOCK_SPACE) + Global::BLOCK_SIZE / 2.0;
-    let y = (matrix.field_height) / 2.0 + matrix.height_offset
-        - ypos as f32 * (Global::BLOCK_SIZE + Global::BLOCK_SPACE)
-        - Global::BLOCK_SIZE / 2.0;
+        // the clock of a race against it, above the level
+        let ypos = height / 2.0 - (config.score_space.1 + 9.0) * (config.block_size + config.block_space);
+        commands
+            .spawn_bundle(TextBundle {
+                style: Style {
+                    align_self: AlignSelf::FlexEnd,
+                    position_type: PositionType::Absolute,
+                    position: Rect {
+                        // Style positions are relative to the window top,left
+                        left: Val::Px(xpos),
+                        top: Val::Px(ypos),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                text: Text::with_section(
+                    timer_text(&matrix.game, &rival.0),
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 0.8 * config.score_size.1,
+                        color: Color::from(config.score_color),
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            })
+            .insert(TextType {
+                id: TextTypes::Time,
+            })
+            .insert(MobileText);
+
+        // the time left of a game against the clock, between that and the level
+        let ypos = height / 2.0 - (config.score_space.1 + 5.5) * (config.block_size + config.block_space);
+        commands
+            .spawn_bundle(TextBundle {
+                style: Style {
+                    align_self: AlignSelf::FlexEnd,
+                    position_type: PositionType::Absolute,
+                    position: Rect {
+                        // Style positions are relative to the window top,left
+                        left: Val::Px(xpos),
+                        top: Val::Px(ypos),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                text: Text::with_section(
+                    countdown_text(&matrix.game),
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: config.score_size.1,
+                        color: Color::from(config.score_color),
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            })
+            .insert(TextType {
+                id: TextTypes::Countdown,
+            })
+            .insert(MobileText);
+
+        // the gravity of a practice game, in the same place as the time left (a Zen doesn't have one)
+        commands
+            .spawn_bundle(TextBundle {
+                style: Style {
+                    align_self: AlignSelf::FlexEnd,
+                    position_type: PositionType::Absolute,
+                    position: Rect {
+                        // Style positions are relative to the window top,left
+                        left: Val::Px(xpos),
+                        top: Val::Px(ypos),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                text: Text::with_section(
+                    gravity_text(&matrix),
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 0.8 * config.score_size.1,
+                        color: Color::from(config.score_color),
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            })
+            .insert(TextType {
+                id: TextTypes::Gravity,
+            })
+            .insert(MobileText);
+
+        // the banner, across the playing field above the status label
+        let xpos = (width - matrix.field_width) / 2.0;
+        let ypos = height / 2.0 - 4.0 * (config.block_size + config.block_space);
+        commands
+            .spawn_bundle(TextBundle {
+                style: Style {
+                    align_self: AlignSelf::FlexEnd,
+                    position_type: PositionType::Absolute,
+                    position: Rect {
+                        // Style positions are relative to the window top,left
+                        left: Val::Px(xpos),
+                        top: Val::Px(ypos),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                text: Text::with_section(
+                    announcement.banner.clone(),
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: config.banner_size,
+                        color: Color::from(config.score_color),
+                    },
+                    TextAlignment {
+                        horizontal: HorizontalAlign::Center,
+                        ..Default::default()
+                    },
+                ),
+                ..Default::default()
+            })
+            .insert(TextType {
+                id: TextTypes::Banner,
+            })
+            .insert(MobileText);
+
+        // the hold label, just above the hold panel
+        let (hold_x, hold_y) = hold_position(&config, &matrix);
+        let xpos = width / 2.0 + hold_x - config.hold_size.0 * (config.block_size + config.block_space) / 2.0;
+        let ypos = height / 2.0 - hold_y - (config.hold_size.1 / 2.0 + 1.5) * (config.block_size + config.block_space);
+        commands
+            .spawn_bundle(TextBundle {
+                style: Style {
+                    align_self: AlignSelf::FlexEnd,
+                    position_type: PositionType::Absolute,
+                    position: Rect {
+                        // Style positions are relative to the window top,left
+                        left: Val::Px(xpos),
+                        top: Val::Px(ypos),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                text: Text::with_section(
+                    "Hold",
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: config.score_size.1,
+                        color: Color::from(config.scorelabel_color),
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            })
+            .insert(TextType {
+                id: TextTypes::Hold,
+            })
+            .insert(MobileText);
+
+        // the next label, just above the next panel
+        let (next_x, next_y) = next_position(&config, &matrix, 0);
+        let xpos = width / 2.0 + next_x - config.hold_size.0 * (config.block_size + config.block_space) / 2.0;
+        let ypos = height / 2.0 - next_y - (config.next_slot / 2.0 + 1.5) * (config.block_size + config.block_space);
+        commands
+            .spawn_bundle(TextBundle {
+                style: Style {
+                    align_self: AlignSelf::FlexEnd,
+                    position_type: PositionType::Absolute,
+                    position: Rect {
+                        // Style positions are relative to the window top,left
+                        left: Val::Px(xpos),
+                        top: Val::Px(ypos),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                text: Text::with_section(
+                    "Next",
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: config.score_size.1,
+                        color: Color::from(config.scorelabel_color),
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            })
+            .insert(TextType {
+                id: TextTypes::Next,
+            })
+            .insert(MobileText);
+
+        // the status label
+        //let window = windows.get_primary_mut().unwrap();
+        let xpos = (width - matrix.field_width) / 2.0;
+        let ypos = height / 2.0;
+        let (status_text, detail_text) = status_text(*state.current(), &matrix, playback.as_deref());
+        commands
+            .spawn_bundle(TextBundle {
+                style: Style {
+                    align_self: AlignSelf::FlexEnd,
+                    position_type: PositionType::Absolute,
+                    position: Rect {
+                        // Style positions are relative to the window top,left
+                        left: Val::Px(xpos),
+                        top: Val::Px(ypos),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                // The status itself, and smaller details underneath (eg the seed at game over)
+                text: Text {
+                    sections: vec![
+                        TextSection {
+                            value: status_text,
+                            style: TextStyle {
+                                font: font.clone(),
+                                font_size: config.statuslabel_size,
+                                color: Color::from(config.statuslabel_color),
+                                //..Default::default()
+                            },
+                        },
+                        TextSection {
+                            value: detail_text,
+                            style: TextStyle {
+                                font, // the last use can consume the font, otherwise we need font.clone()
+                                font_size: config.score_size.1,
+                                color: Color::from(config.statuslabel_color),
+                            },
+                        },
+                    ],
+                    alignment: TextAlignment {
+                        horizontal: HorizontalAlign::Center,
+                        ..Default::default()
+                    },
+                },
+                ..Default::default()
+            })
+            .insert(TextType {
+                id: TextTypes::Status,
+            })
+            .insert(MobileText);
+    }
+}
+
+// ========================================
+// Utility functions
+
+/// Print a text version of the occupation grid. Only used in debug builds
+#[cfg(debug_assertions)]
+fn pretty_print(matrix: &Matrix) {
+    let board = matrix.game.board();
+    // Higher rows numbers (y) are at the bottom
+    for y in 0..board.height() {
+        let slice_start = (board.width() * y) as usize;
+        let slice_end = (board.width() * (y + 1)) as usize; // not included in slice
+        let slice = &(board.occupation())[slice_start..slice_end];
+        println!("occupation {:2} {:?}", y, slice);
+    }
+}
+
+/// Centre of the hold panel, in the same co-ordinates as the sprites
+fn hold_position(config: &GameConfig, matrix: &Matrix) -> (f32, f32) {
+    let x = -(matrix.field_width) / 2.0
+        - (config.hold_space.0 + config.hold_size.0 / 2.0) * (config.block_size + config.block_space);
+    let y = (matrix.field_height) / 2.0 - matrix.height_offset
+        - (config.hold_space.1 + config.hold_size.1 / 2.0) * (config.block_size + config.block_space);
 
     (x, y)
 }
 
-/// Rotate a block within a bounding box
-fn rotate_block(
-    x: i32,
-    y: i32,
-    min_x: i32,
-    min_y: i32,
-    size_x: i32,
-    size_y: i32,
-    desired_rot: i32,
-) -> (i32, i32) {
-    let rel_x = x - min_x; // Relative position of the current block within the tetromino bounding box
-    let rel_y = y - min_y;
-
-    let mut new_x = x; // Default to no change
-    let mut new_y = y;
-
-    // Brute force approach. Aesthetically offensive but not as inefficient as it seems, and much easier to debug
-    //#[rustfmt::skip] // Much easier to read with horizontal formatting
-                       // Sadly cargo build is unhappy about it at the moment, so we comment it out until the feature is available.
-                       
-    match (size_x, size_y, desired_rot, rel_x, rel_y) {
-        //(2, 2, _) => { } // Shouldn't happen because we avoid rotating squares
-
-        // VBar, rotate+
-        (1, 4, 1, 0, 0) => { new_x = x - 1; new_y = y + 1; }
-        (1, 4, 1, 0, 1) => { new_x = x;     new_y = y;     }
-        (1, 4, 1, 0, 2) => { new_x = x + 1; new_y = y - 1; }
-        (1, 4, 1, 0, 3) => { new_x = x + 2; new_y = y - 2; }
-
-        // VBar, rotate-
-        (1, 4, -1, 0, 0) => { new_x = x + 1; new_y = y + 1; }
-        (1, 4, -1, 0, 1) => { new_x = x;     new_y = y;     }
-        (1, 4, -1, 0, 2) => { new_x = x - 1; new_y = y - 1; }
-        (1, 4, -1, 0, 3) => { new_x = x - 2; new_y = y - 2; }
-
-        // HBar, rotate+
-        (4, 1, 1, 0, 0) => { new_x = x + 1; new_y = y - 1; }
-        (4, 1, 1, 1, 0) => { new_x = x;     new_y = y;     }
-        (4, 1, 1, 2, 0) => { new_x = x - 1; new_y = y + 1; }
-        (4, 1, 1, 3, 0) => { new_x = x - 2; new_y = y + 2; }
-
-        // HBar, rotate-
-        (4, 1, -1, 0, 0) => { new_x = x + 2; new_y = y + 2; }
-        (4, 1, -1, 1, 0) => { new_x = x + 1; new_y = y + 1; }
-        (4, 1, -1, 2, 0) => { new_x = x;     new_y = y;     }
-        (4, 1, -1, 3, 0) => { new_x = x - 1; new_y = y - 1; }
-
-        // VRect, rotate-
-        (2, 3, -1, 0, 0) => { new_x = x;     new_y = y + 1; }
-        (2, 3, -1, 1, 0) => { new_x = x - 1; new_y = y;     }
-        (2, 3, -1, 0, 1) => { new_x = x + 1; new_y = y;     }
-        (2, 3, -1, 1, 1) => { new_x = x;     new_y = y - 1; }
-        (2, 3, -1, 0, 2) => { new_x = x + 2; new_y = y - 1; }
-        (2, 3, -1, 1, 2) => { new_x = x + 1; new_y = y - 2; }
-
-        // VRect, rotate+
-        (2, 3, 1, 0, 0) => { new_x = x + 1; new_y = y;     }
-        (2, 3, 1, 1, 0) => { new_x = x;     new_y = y + 1; }
-        (2, 3, 1, 0, 1) => { new_x = x;     new_y = y - 1; }
-        (2, 3, 1, 1, 1) => { new_x = x - 1; new_y = y;     }
-        (2, 3, 1, 0, 2) => { new_x = x - 1; new_y = y - 2; }
-        (2, 3, 1, 1, 2) => { new_x = x - 2; new_y = y - 1; }
-
-        // HRect, rotate+
-        (3, 2, 1, 0, 0) => { new_x = x + 2; new_y = y;     }
-        (3, 2, 1, 1, 0) => { new_x = x + 1; new_y = y + 1; }
-        (3, 2, 1, 2, 0) => { new_x = x;     new_y = y + 2; }
-        (3, 2, 1, 0, 1) => { new_x = x + 1; new_y = y - 1; }
-        (3, 2, 1, 1, 1) => { new_x = x;     new_y = y;     }
-        (3, 2, 1, 2, 1) => { new_x = x - 1; new_y = y + 1; }
-
-        // HRect, rotate-
-        (3, 2, -1, 0, 0) => { new_x = x;     new_y = y + 2; }
-        (3, 2, -1, 1, 0) => { new_x = x - 1; new_y = y + 1; }
-        (3, 2, -1, 2, 0) => { new_x = x - 2; new_y = y;     }
-        (3, 2, -1, 0, 1) => { new_x = x + 1; new_y = y + 1; }
-        (3, 2, -1, 1, 1) => { new_x = x;     new_y = y;     }
-        (3, 2, -1, 2, 1) => { new_x = x - 1; new_y = y - 1; }
-
-        // What?
-        (x, y, r, rx, ry) => {
-            println!("Unknown {} {} {} {} {}", x, y, r, rx, ry);
+/// Centre of a slot in the next panel (0 = the next tetromino), in the same co-ordinates as the sprites
+fn next_position(config: &GameConfig, matrix: &Matrix, slot: usize) -> (f32, f32) {
+    let (x, hold_y) = hold_position(config, matrix);
+    let y = hold_y
+        - (config.hold_size.1 / 2.0 + config.next_space + (slot as f32 + 0.5) * config.next_slot)
+            * (config.block_size + config.block_space);
+
+    (x, y)
+}
+
+/// Create the sprites for a tetromino centred on (xpos, ypos), outside the playing grid.
+/// Scale shrinks (or grows) the blocks, eg for the next panel
+fn spawn_panel_blocks<C: Component + Copy>(
+    commands: &mut Commands,
+    config: &GameConfig,
+    tet_type: TetrominoType,
+    (xpos, ypos): (f32, f32),
+    scale: f32,
+    marker: C,
+) {
+    let indices = tet_type.blocks();
+    let min_x = indices.iter().map(|index| index.0).min().unwrap_or(0);
+    let max_x = indices.iter().map(|index| index.0).max().unwrap_or(0);
+    let min_y = indices.iter().map(|index| index.1).min().unwrap_or(0);
+    let max_y = indices.iter().map(|index| index.1).max().unwrap_or(0);
+
+    // Offsets from the centre of the tetromino, so it sits in the middle of the panel
+    let centre_x = (min_x + max_x) as f32 / 2.0;
+    let centre_y = (min_y + max_y) as f32 / 2.0;
+
+    for (block, index) in Tetromino::blocks_from_type(tet_type).into_iter().zip(indices.iter()) {
+        let x = xpos + (index.0 as f32 - centre_x) * (config.block_size + config.block_space) * scale;
+        let y = ypos - (index.1 as f32 - centre_y) * (config.block_size + config.block_space) * scale;
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    custom_size: Some(Vec2::new(config.block_size * scale, config.block_size * scale)),
+                    color: block.color,
+                    ..Default::default() // Sprite defaults
+                },
+                transform: Transform::from_translation(Vec3::new(x, y, 1.0)),
+                ..Default::default() // Sprite bundle defaults
+            })
+            .insert(marker);
+    }
+}
+
+/// The name of a key in the bindings file
+fn key_name(key: KeyCode) -> String {
+    format!("{:?}", key)
+}
+
+/// The key with this name, if it is one that can be bound
+fn key_code(name: &str) -> Option<KeyCode> {
+    KEYS.iter().copied().find(|&key| key_name(key) == name)
+}
+
+/// The name of a gamepad button in the bindings file
+fn button_name(button_type: GamepadButtonType) -> String {
+    format!("{:?}", button_type)
+}
+
+/// The gamepad button with this name, if it is one that can be bound
+fn button_type(name: &str) -> Option<GamepadButtonType> {
+    BUTTONS.iter().copied().find(|&button_type| button_name(button_type) == name)
+}
+
+/// Create a screen over everything else, with some text in the middle (the settings, the high scores)
+fn spawn_screen(commands: &mut Commands, config: &GameConfig, asset_server: &AssetServer, text: &str) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            color: UiColor(Color::rgba(0.0, 0.0, 0.0, 0.85)),
+            ..Default::default()
+        })
+        .insert(Screen)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        text,
+                        TextStyle {
+                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                            font_size: config.score_size.1 * 0.8,
+                            color: Color::from(config.scorelabel_color),
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(ScreenText);
+        });
+}
+
+/// The choices of a menu, one per line, the selected one marked
+fn menu_text(menu: &Menu) -> String {
+    let lines: Vec<String> = menu
+        .items
+        .iter()
+        .enumerate()
+        .map(|(index, (label, _item))| format!("{} {}", if index == menu.selected { ">" } else { "  " }, label))
+        .collect();
+    lines.join("\n")
+}
+
+/// Above the game over menu: the name being typed for a new high score, or the top of the table with this game marked
+fn game_over_heading(scores: &HighScores, new_score: Option<&NewScore>) -> String {
+    if let Some(new_score) = new_score.filter(|new_score| new_score.typing) {
+        return format!(
+            "New high score, number {}!\nName: {}_\nReturn: done\n\n",
+            new_score.rank + 1,
+            new_score.entry.name
+        );
+    }
+
+    let this_game = new_score.map(|new_score| new_score.rank);
+    let line = |rank: usize, entry: &Entry| {
+        let marker = if this_game == Some(rank) { ">" } else { "  " };
+        format!("{} {}  {}", marker, score_line(rank, entry), result(scores, entry))
+    };
+    let mut lines = vec!["High scores".to_string()];
+    for (rank, entry) in scores.entries().iter().enumerate().take(GAME_OVER_SCORES) {
+        lines.push(line(rank, entry));
+    }
+    // This game always makes it onto the screen, even if it isn't near the top
+    if let Some(rank) = this_game.filter(|&rank| rank >= GAME_OVER_SCORES) {
+        if let Some(entry) = scores.entries().get(rank) {
+            lines.push("  ...".to_string());
+            lines.push(line(rank, entry));
         }
     }
+    if scores.entries().is_empty() {
+        lines.push("No high scores yet".to_string());
+    }
+    lines.join("\n") + "\n\n"
+}
+
+/// The whole of a mode's high score table, for the high scores screen
+fn high_scores_text(mode: Mode, scores: &HighScores) -> String {
+    let mut lines = vec![format!("High scores: {}\n", mode.name())];
+    for (rank, entry) in scores.entries().iter().enumerate() {
+        let details = match scores.ranking() {
+            Ranking::Score => format!("Level {}  {} lines  {}", entry.level, entry.lines, clock(entry.duration())),
+            Ranking::Time => format!("PPS {:.2}  {} points", entry.pieces_per_second(), entry.score),
+        };
+        lines.push(format!(
+            "{}  {}  {}  {}  {}",
+            score_line(rank, entry),
+            result(scores, entry),
+            details,
+            entry.mode,
+            entry.date
+        ));
+    }
+    if scores.entries().is_empty() {
+        lines.push("No high scores yet".to_string());
+    }
+    lines.push("\nLeft/Right: other modes   Escape: back".to_string());
+    lines.join("\n")
+}
+
+/// The start of a line of the high score table: the place and the name
+fn score_line(rank: usize, entry: &Entry) -> String {
+    format!("{:2}. {}", rank + 1, entry.name)
+}
+
+/// What a game in the table is ranked on: the score, or the time for a race against the clock
+fn result(scores: &HighScores, entry: &Entry) -> String {
+    match scores.ranking() {
+        Ranking::Score => entry.score.to_string(),
+        Ranking::Time => stopwatch(entry.duration()),
+    }
+}
+
+/// A length of time as minutes and seconds, eg 6:52
+fn clock(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// A length of time to the millisecond, eg 0:58.214
+fn stopwatch(duration: Duration) -> String {
+    format!("{}.{:03}", clock(duration), duration.subsec_millis())
+}
+
+/// How far ahead (-) or behind (+) a time is, eg -0.214
+fn difference(time: Duration, best: Duration) -> String {
+    if time >= best {
+        format!("+{:.3}", (time - best).as_secs_f32())
+    } else {
+        format!("-{:.3}", (best - time).as_secs_f32())
+    }
+}
 
-    (new_x, new_y)
+/// Tetrominoes locked per second so far
+fn pieces_per_second(game: &Game) -> f32 {
+    if game.time().is_zero() {
+        return 0.0;
+    }
+    game.stats().pieces as f32 / game.time().as_secs_f32()
+}
+
+/// The level, or for a Master the level it counts and the next section stop (eg 042/100)
+fn level_text(game: &Game) -> String {
+    match game.master_level() {
+        Some(level) => format!(" {:03}/{}", level, master::section_stop(level) + 1),
+        None => format!(" {:02}", game.level()),
+    }
+}
+
+/// Seconds between automatic drops: the drop speed for the level of the game, or the gravity chosen by hand
+fn drop_interval(config: &GameConfig, matrix: &Matrix) -> f32 {
+    let speed = match matrix.gravity {
+        Some(level) => game::drop_speed(level),
+        None => matrix.game.drop_speed(),
+    };
+    config.drop_speed_factor * speed
+}
+
+/// The gravity chosen by hand and how many times the heap has reached the top, for a Zen. Nothing for other modes
+fn gravity_text(matrix: &Matrix) -> String {
+    match matrix.gravity {
+        Some(level) => format!("Gravity {} (-/+)\nTop outs {}", level, matrix.game.stats().top_outs),
+        None => "".to_string(),
+    }
+}
+
+/// The time left, for a mode with a time limit (an Ultra). Nothing for other modes
+fn countdown_text(game: &Game) -> String {
+    match game.time_limit() {
+        Some(limit) => format!("Time left {}", stopwatch(limit.saturating_sub(game.time()))),
+        None => "".to_string(),
+    }
+}
+
+/// The clock for a race against it (a Sprint or a Dig): the time, the lines to go, pieces per second, and the latest split
+/// against the same split of the personal best. Nothing for other modes
+fn timer_text(game: &Game, rival: &[Duration]) -> String {
+    let settings = game.settings();
+    if settings.mode.ranking() != Ranking::Time {
+        return "".to_string();
+    }
+    let stats = game.stats();
+    let (what, done, goal) = match settings.mode {
+        Mode::Dig => ("Garbage", stats.garbage_lines, settings.dig_lines),
+        _ => ("Lines", stats.lines, settings.sprint_lines),
+    };
+    let mut lines = vec![
+        format!("Time {}", stopwatch(game.time())),
+        format!("{} {}/{}  PPS {:.2}", what, done.min(goal), goal, pieces_per_second(game)),
+    ];
+    if let Some(&split) = game.splits().last() {
+        let index = game.splits().len() - 1;
+        let mut line = format!("{} lines {}", (index + 1) * Settings::SPLIT_LINES, stopwatch(split));
+        if let Some(&best) = rival.get(index) {
+            line += &format!(" ({})", difference(split, best));
+        }
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+/// The status shown over the playing field in each state, and smaller details underneath (eg the game's statistics)
+fn status_text(state: AppState, matrix: &Matrix, playback: Option<&Playback>) -> (String, String) {
+    match state {
+        AppState::MainMenu => ("Tetris".to_string(), "".to_string()),
+        AppState::Paused => ("Paused".to_string(), "".to_string()),
+        AppState::GameOver if matches!(playback, Some(playback) if playback.0.is_finished()) => {
+            ("Replay over".to_string(), game_over_detail(matrix))
+        }
+        AppState::GameOver if matrix.game.is_finished() && matrix.game.time_limit().is_some() => {
+            ("Time's up".to_string(), game_over_detail(matrix))
+        }
+        AppState::GameOver if matrix.game.is_finished() => ("Finished".to_string(), game_over_detail(matrix)),
+        AppState::GameOver => ("Game over".to_string(), game_over_detail(matrix)),
+        AppState::Playing | AppState::Settings | AppState::HighScores => ("".to_string(), "".to_string()),
+    }
+}
+
+/// The high score file for a mode: the one given (or highscores.toml) for a Marathon, and the same name with the
+/// mode on the end for the others, eg highscores-sprint.toml
+fn mode_scores_path(path: &Path, mode: Mode) -> PathBuf {
+    if mode == Mode::Marathon {
+        return path.to_path_buf();
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut file_name = format!("{}-{}", stem, mode.name().to_lowercase());
+    if let Some(extension) = path.extension() {
+        file_name = format!("{}.{}", file_name, extension.to_string_lossy());
+    }
+    path.with_file_name(file_name)
+}
+
+/// Read a high score table, or start a new one.
+/// The high scores can't be put right by hand like the other files, so one we can't read is moved out of the way
+/// (to <file>.bad) rather than written over by the next high score, and the table starts again
+fn load_scores(path: &Path, size: usize, ranking: Ranking) -> HighScores {
+    match HighScores::load(path, size, ranking) {
+        Ok(scores) => scores,
+        Err(ScoresError::Io(error)) if error.kind() == ErrorKind::NotFound => HighScores::new(size, ranking),
+        Err(ScoresError::Io(error)) => {
+            eprintln!("Can't read the high scores from {}: {}", path.display(), error);
+            HighScores::new(size, ranking)
+        }
+        Err(error) => {
+            let mut bad_path = path.to_path_buf().into_os_string();
+            bad_path.push(".bad");
+            let kept = match fs::rename(path, &bad_path) {
+                Ok(()) => format!(" (the old file is now {})", PathBuf::from(&bad_path).display()),
+                Err(_) => "".to_string(),
+            };
+            eprintln!("Can't use {}: {}. Starting a new high score table{}", path.display(), error, kept);
+            HighScores::new(size, ranking)
+        }
+    }
+}
+
+/// Write the recorded inputs to the file given on the command line
+fn save_recording(recording: &Recording) {
+    if let Err(error) = recording.replay.save(&recording.path) {
+        eprintln!("Can't save the replay to {}: {}", recording.path.display(), error);
+    }
+}
+
+/// The extra information shown under 'Game over': the seed, how long the game took and the game's statistics
+fn game_over_detail(matrix: &Matrix) -> String {
+    let stats = matrix.game.stats();
+    let garbage = match matrix.game.settings().mode {
+        Mode::Dig => format!("  Garbage {}", stats.garbage_lines),
+        _ => "".to_string(),
+    };
+    format!(
+        "\nSeed {}\nTime {}  PPS {:.2}\nPieces {}  Lines {}{}  Tetrises {}\nT-spins {}  Best combo {}  Perfect clears {}",
+        matrix.game.seed(),
+        stopwatch(matrix.game.time()),
+        pieces_per_second(&matrix.game),
+        stats.pieces,
+        stats.lines,
+        garbage,
+        stats.tetrises,
+        stats.t_spins,
+        stats.max_combo,
+        stats.perfect_clears
+    )
+}
+
+/// Calculate screen position from the block co-ordinates in the playing grid
+fn grid_position(config: &GameConfig, matrix: &Matrix, xpos: i32, ypos: i32) -> (f32, f32) {
+    let x =
+        -(matrix.field_width) / 2.0 + xpos as f32 * (config.block_size + config.block_space) + config.block_size / 2.0;
+    let y = (matrix.field_height) / 2.0 + matrix.height_offset
+        - ypos as f32 * (config.block_size + config.block_space)
+        - config.block_size / 2.0;
+
+    (x, y)
 }
@@ -0,0 +1,101 @@
+//! The timing of a Master game: gravity, entry delay (ARE) and lock delay, by level.
+//!
+//! A Master game counts its own level, from 0 to 999 in sections of 100, the way the arcade games (TGM) do.
+//! Each new tetromino adds 1 and each line cleared adds 1, but only clearing lines gets past the last level of a
+//! section (x99, or 998 at the end). The game is finished at 999.
+//!
+//! The tables are in frames at 60 frames a second, as they are usually published. Gravity is in 1/256ths of
+//! a row per frame, so 256 is one row every frame (1G) and 5120 is 20 rows a frame (20G): a new tetromino
+//! lands as soon as it appears. See <https://tetris.wiki/Tetris_The_Grand_Master_2>
+
+use std::time::Duration;
+
+/// The level that finishes the game
+pub const MAX_LEVEL: usize = 999;
+
+/// Levels in a section
+pub const SECTION: usize = 100;
+
+/// Gravity of one row every frame
+pub const ONE_G: u64 = 256;
+
+/// Gravity that drops a tetromino to the bottom of any field in one frame
+pub const TWENTY_G: u64 = 20 * ONE_G;
+
+/// Frames in a second
+pub const FRAMES_PER_SECOND: u64 = 60;
+
+/// Gravity from each level up to the next one, in 1/256ths of a row per frame
+const GRAVITY: [(usize, u64); 30] = [
+    (0, 4),
+    (30, 6),
+    (35, 8),
+    (40, 10),
+    (50, 12),
+    (60, 16),
+    (70, 32),
+    (80, 48),
+    (90, 64),
+    (100, 80),
+    (120, 96),
+    (140, 112),
+    (160, 128),
+    (170, 144),
+    (200, 4), // the first surprise: back to the start, then a steep climb
+    (220, 32),
+    (230, 64),
+    (233, 96),
+    (236, 128),
+    (239, 160),
+    (243, 192),
+    (247, 224),
+    (251, ONE_G),
+    (300, 2 * ONE_G),
+    (330, 3 * ONE_G),
+    (360, 4 * ONE_G),
+    (400, 5 * ONE_G),
+    (420, 4 * ONE_G),
+    (450, 3 * ONE_G),
+    (500, TWENTY_G),
+];
+
+/// Entry delay (ARE, frames between a tetromino locking and the next one appearing) and lock delay (frames on
+/// the ground) from each level. Once the gravity is 20G all that is left to make harder is the time
+const TIMING: [(usize, u64, u64); 5] = [(0, 25, 30), (700, 16, 30), (800, 12, 30), (900, 12, 17), (MAX_LEVEL, 12, 17)];
+
+/// How fast tetrominoes fall at this level, in 1/256ths of a row per frame
+pub fn gravity(level: usize) -> u64 {
+    GRAVITY.iter().rev().find(|&&(from, _)| level >= from).map_or(GRAVITY[0].1, |&(_, gravity)| gravity)
+}
+
+/// The time between a tetromino locking and the next one appearing at this level
+pub fn entry_delay(level: usize) -> Duration {
+    frames(timing(level).1)
+}
+
+/// How long a tetromino can stay on the ground before it locks at this level
+pub fn lock_delay(level: usize) -> Duration {
+    frames(timing(level).2)
+}
+
+/// The next level that only clearing lines gets past: the end of the section, or 998 in the last one
+pub fn section_stop(level: usize) -> usize {
+    (level / SECTION * SECTION + SECTION - 1).min(MAX_LEVEL - 1)
+}
+
+/// How far a tetromino falls in this much time at this gravity, in 1/256ths of a row per frame times
+/// microseconds, so that nothing is lost to rounding from one frame to the next. `ROW` of it is one row
+pub fn fall(gravity: u64, elapsed: Duration) -> u64 {
+    gravity * FRAMES_PER_SECOND * elapsed.as_micros() as u64
+}
+
+/// One row of `fall`
+pub const ROW: u64 = ONE_G * 1_000_000;
+
+fn timing(level: usize) -> (usize, u64, u64) {
+    *TIMING.iter().rev().find(|&&(from, _, _)| level >= from).unwrap_or(&TIMING[0])
+}
+
+fn frames(frames: u64) -> Duration {
+    Duration::from_micros(frames * 1_000_000 / FRAMES_PER_SECOND)
+}
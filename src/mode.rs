@@ -0,0 +1,95 @@
+//! The kinds of game that can be played: what ends them, and what counts as doing well.
+//!
+//! The rules are the same in every mode, a mode only adds a goal on top of them. The numbers for each goal
+//! (eg how many lines a Sprint is) are in `Settings`, so that a replay keeps them.
+
+use crate::game::Settings;
+use crate::scores::Ranking;
+
+/// A kind of game
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Mode {
+    /// The classic: carry on until the heap reaches the top, for as many points as you can
+    Marathon,
+    /// Clear a number of lines (`Settings::sprint_lines`) as fast as you can
+    Sprint,
+    /// As many points as you can before the time (`Settings::ultra_time`) runs out
+    Ultra,
+    /// Dig through rows of garbage (`Settings::dig_lines`), each with a hole in it, as fast as you can.
+    /// Also known as cheese
+    Dig,
+    /// Practice: reaching the top doesn't end the game (see `Settings::zen_top_out`), and the gravity is up to you
+    Zen,
+    /// The arcade way: a level from 0 to 999 that brings faster gravity (up to 20G) and less time (see `master`)
+    Master,
+}
+
+impl Mode {
+    /// Every mode, in the order they are offered
+    pub const ALL: [Mode; 6] = [Mode::Marathon, Mode::Sprint, Mode::Ultra, Mode::Dig, Mode::Zen, Mode::Master];
+
+    /// The name used for this mode in files
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Marathon => "Marathon",
+            Mode::Sprint => "Sprint",
+            Mode::Ultra => "Ultra",
+            Mode::Dig => "Dig",
+            Mode::Zen => "Zen",
+            Mode::Master => "Master",
+        }
+    }
+
+    /// The mode with this name, if there is one
+    pub fn from_name(name: &str) -> Option<Mode> {
+        Mode::ALL.iter().copied().find(|mode| mode.name() == name)
+    }
+
+    /// What makes one game better than another in the high score table
+    pub fn ranking(self) -> Ranking {
+        match self {
+            Mode::Marathon | Mode::Ultra | Mode::Zen | Mode::Master => Ranking::Score,
+            Mode::Sprint | Mode::Dig => Ranking::Time,
+        }
+    }
+
+    /// Do games of this mode go in a high score table? Not if they never end
+    pub fn keeps_scores(self) -> bool {
+        self != Mode::Zen
+    }
+
+    /// What happens when the heap reaches the top: the end of the game, except in a Zen
+    pub fn top_out(self, settings: &Settings) -> TopOut {
+        match self {
+            Mode::Zen => settings.zen_top_out,
+            _ => TopOut::End,
+        }
+    }
+}
+
+/// What happens when a tetromino locks above the field (or garbage pushes the heap there)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TopOut {
+    /// The game is over
+    End,
+    /// The top rows of the heap (`Settings::zen_clear_rows`) are taken away, and the game goes on
+    ClearTop,
+    /// The whole heap is taken away, and the game goes on
+    Reset,
+}
+
+impl TopOut {
+    /// The name used for this in files
+    pub fn name(self) -> &'static str {
+        match self {
+            TopOut::End => "end",
+            TopOut::ClearTop => "clear",
+            TopOut::Reset => "reset",
+        }
+    }
+
+    /// The one with this name, if there is one
+    pub fn from_name(name: &str) -> Option<TopOut> {
+        [TopOut::End, TopOut::ClearTop, TopOut::Reset].into_iter().find(|top_out| top_out.name() == name)
+    }
+}
@@ -0,0 +1,146 @@
+//! Ways of choosing the next tetromino.
+//!
+//! Pure random choice allows long 'droughts' (eg no I for 30 tetrominoes), so most modern games use
+//! something that evens things out. The `RandomizerKind` in the game settings picks which one we use.
+
+use crate::tetromino::TetrominoType;
+use rand::seq::SliceRandom;
+use rand::{Rng, RngCore};
+use std::collections::VecDeque;
+use std::fmt::Debug;
+
+/// Chooses a sequence of tetrominoes, using the random numbers it is given
+pub trait Randomizer: Debug + Send + Sync {
+    /// The next tetromino in the sequence
+    fn next(&mut self, rng: &mut dyn RngCore) -> TetrominoType;
+}
+
+/// The randomizers we know about, as selected in the game settings
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RandomizerKind {
+    /// Every tetromino is equally likely every time (the original behaviour)
+    Random,
+    /// A shuffled bag of all seven, refilled when empty (the guideline)
+    Bag7,
+    /// A shuffled bag of two of each
+    Bag14,
+    /// Avoid the last four tetrominoes, rerolling a few times (Tetris The Grand Master 2)
+    Tgm,
+}
+
+impl RandomizerKind {
+    /// Every kind
+    pub const ALL: [RandomizerKind; 4] = [
+        RandomizerKind::Random,
+        RandomizerKind::Bag7,
+        RandomizerKind::Bag14,
+        RandomizerKind::Tgm,
+    ];
+
+    /// The name used for this kind in files
+    pub fn name(self) -> &'static str {
+        match self {
+            RandomizerKind::Random => "random",
+            RandomizerKind::Bag7 => "bag7",
+            RandomizerKind::Bag14 => "bag14",
+            RandomizerKind::Tgm => "tgm",
+        }
+    }
+
+    /// The kind with this name, if there is one
+    pub fn from_name(name: &str) -> Option<RandomizerKind> {
+        RandomizerKind::ALL.iter().copied().find(|kind| kind.name() == name)
+    }
+
+    /// A new randomizer of this kind, at the start of its sequence
+    pub fn create(self) -> Box<dyn Randomizer> {
+        match self {
+            RandomizerKind::Random => Box::new(PureRandom),
+            RandomizerKind::Bag7 => Box::new(Bag::new(1)),
+            RandomizerKind::Bag14 => Box::new(Bag::new(2)),
+            RandomizerKind::Tgm => Box::new(History::new(6)),
+        }
+    }
+}
+
+/// Every tetromino is equally likely every time
+#[derive(Debug, Default)]
+pub struct PureRandom;
+
+impl Randomizer for PureRandom {
+    fn next(&mut self, rng: &mut dyn RngCore) -> TetrominoType {
+        rng.gen()
+    }
+}
+
+/// A bag holding `copies` of each tetromino, drawn in a random order and refilled when it is empty
+#[derive(Debug)]
+pub struct Bag {
+    copies: usize,
+    contents: Vec<TetrominoType>,
+}
+
+impl Bag {
+    pub fn new(copies: usize) -> Bag {
+        Bag {
+            copies: copies.max(1),
+            contents: Vec::new(),
+        }
+    }
+}
+
+impl Randomizer for Bag {
+    fn next(&mut self, rng: &mut dyn RngCore) -> TetrominoType {
+        if self.contents.is_empty() {
+            for _copy in 0..self.copies {
+                self.contents.extend_from_slice(&TetrominoType::ALL);
+            }
+            self.contents.shuffle(rng);
+        }
+        // A shuffled bag can be drawn from either end
+        self.contents.pop().unwrap_or(TetrominoType::I)
+    }
+}
+
+/// Remembers the last four tetrominoes and rerolls (up to `rolls` times) to avoid them
+///
+/// The history starts as Z, S, S, Z and the first tetromino is never S, Z or O, so a game never starts
+/// with an overhang.
+#[derive(Debug)]
+pub struct History {
+    rolls: usize,
+    history: VecDeque<TetrominoType>,
+    first: bool,
+}
+
+impl History {
+    pub fn new(rolls: usize) -> History {
+        History {
+            rolls: rolls.max(1),
+            history: VecDeque::from(vec![TetrominoType::Z, TetrominoType::S, TetrominoType::S, TetrominoType::Z]),
+            first: true,
+        }
+    }
+}
+
+impl Randomizer for History {
+    fn next(&mut self, rng: &mut dyn RngCore) -> TetrominoType {
+        let tetromino_type = if self.first {
+            self.first = false;
+            [TetrominoType::I, TetrominoType::J, TetrominoType::L, TetrominoType::T][rng.gen_range(0, 4)]
+        } else {
+            let mut roll = rng.gen();
+            for _reroll in 1..self.rolls {
+                if !self.history.contains(&roll) {
+                    break;
+                }
+                roll = rng.gen();
+            }
+            roll
+        };
+
+        self.history.pop_front();
+        self.history.push_back(tetromino_type);
+        tetromino_type
+    }
+}
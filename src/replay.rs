@@ -0,0 +1,324 @@
+//! Recording the inputs of a game so that it can be played back exactly.
+//!
+//! A game is deterministic: the same settings and seed, with the same inputs in the same frames (each lasting the
+//! same time), always give the same game. So a replay only needs those, not what happened on the board.
+//!
+//! Replays are saved as text, a header and then one line per frame, with the length of the frame in
+//! microseconds followed by its inputs:
+//! ```text
+//! tetris-replay 12
+//! seed 1234
+//! width 10
+//! height 20
+//! start 4 4
+//! max_level 20
+//! preview 5
+//! randomizer bag7
+//! lock_delay 500000000
+//! lock_resets 15
+//! mode Sprint
+//! sprint_lines 40
+//! ultra_time 120000000000
+//! ultra_levels false
+//! dig_lines 18
+//! dig_rows 9
+//! zen_top_out clear
+//! zen_clear_rows 8
+//! 16667 RotateClockwise
+//! 16667
+//! 16666 MoveLeft Gravity
+//! ```
+//! The lock delay and the Ultra time are in nanoseconds, so any time in the settings is saved exactly.
+//! The number after `tetris-replay` is the format version. If the format changes, or the rules change so that
+//! the same inputs give a different game, so does the version, and old replays are refused rather than played
+//! back wrongly.
+
+use crate::game::{Action, Game, Settings};
+use crate::mode::{Mode, TopOut};
+use crate::randomizer::RandomizerKind;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::mem;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The version of the replay format we read and write
+pub const VERSION: u32 = 12; // 2: hard drop lands at once, 3: frame times for the lock delay, 4: T-spin scores, 5: combos and back-to-back, 6: perfect clears, 7: modes, 8: Ultra, 9: Dig, 10: Zen, 11: Master, 12: lock delay and Ultra time in nanoseconds
+
+/// The first word of every replay file
+const HEADER: &str = "tetris-replay";
+
+/// Something fed into the game during a frame
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Input {
+    /// The player asked for something (`Game::apply`)
+    Action(Action),
+    /// The automatic drop timer went off (`Game::gravity`)
+    Gravity,
+}
+
+impl Input {
+    /// Feed this input into a game
+    pub fn apply_to(self, game: &mut Game) -> bool {
+        match self {
+            Input::Action(action) => game.apply(action),
+            Input::Gravity => game.gravity(),
+        }
+    }
+}
+
+/// One frame of a recorded game
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// The time since the previous frame, as passed to `Game::step`. Whole microseconds, so it can be saved exactly
+    pub elapsed: Duration,
+    /// The inputs, in the order they were applied
+    pub inputs: Vec<Input>,
+}
+
+/// Everything needed to play a game again: the settings, the seed and every frame
+#[derive(Debug, Clone)]
+pub struct Replay {
+    /// The settings of the recorded game, the seed is always set
+    pub settings: Settings,
+    /// Every frame of the game, the first one first
+    pub frames: Vec<Frame>,
+    /// Inputs for the frame being recorded
+    pending: Vec<Input>,
+}
+
+impl Replay {
+    /// An empty recording of a game with these settings and seed
+    pub fn new(settings: &Settings, seed: u64) -> Replay {
+        Replay {
+            settings: Settings {
+                seed: Some(seed),
+                ..settings.clone()
+            },
+            frames: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Round a frame time to what can be saved in a replay.
+    /// The game must be given the rounded time, or it won't play back exactly the same
+    pub fn frame_time(elapsed: Duration) -> Duration {
+        Duration::from_micros(elapsed.as_micros() as u64)
+    }
+
+    /// The seed for the game's random choices
+    pub fn seed(&self) -> u64 {
+        self.settings.seed.unwrap_or_default()
+    }
+
+    /// Add an input to the current frame
+    pub fn record(&mut self, input: Input) {
+        self.pending.push(input);
+    }
+
+    /// The current frame is over (the game has stepped this far), later inputs go in the next one
+    pub fn next_frame(&mut self, elapsed: Duration) {
+        self.frames.push(Frame {
+            elapsed: Replay::frame_time(elapsed),
+            inputs: mem::take(&mut self.pending),
+        });
+    }
+
+    /// Play the whole recording into a game, which is reset first. Nothing here needs a window
+    pub fn play(&self, game: &mut Game) {
+        game.reset_with_seed(self.seed());
+        for frame in self.frames.iter() {
+            for &input in frame.inputs.iter() {
+                input.apply_to(game);
+            }
+            game.step(frame.elapsed);
+        }
+    }
+
+    /// Write the replay to a file
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    /// Read a replay from a file
+    pub fn load(path: &Path) -> Result<Replay, ReplayError> {
+        fs::read_to_string(path)?.parse()
+    }
+}
+
+impl fmt::Display for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let settings = &self.settings;
+        writeln!(f, "{} {}", HEADER, VERSION)?;
+        writeln!(f, "seed {}", self.seed())?;
+        writeln!(f, "width {}", settings.width)?;
+        writeln!(f, "height {}", settings.height)?;
+        writeln!(f, "start {} {}", settings.start_pos.0, settings.start_pos.1)?;
+        writeln!(f, "max_level {}", settings.max_level)?;
+        writeln!(f, "preview {}", settings.preview)?;
+        writeln!(f, "randomizer {}", settings.randomizer.name())?;
+        writeln!(f, "lock_delay {}", settings.lock_delay.as_nanos())?;
+        writeln!(f, "lock_resets {}", settings.lock_resets)?;
+        writeln!(f, "mode {}", settings.mode.name())?;
+        writeln!(f, "sprint_lines {}", settings.sprint_lines)?;
+        writeln!(f, "ultra_time {}", settings.ultra_time.as_nanos())?;
+        writeln!(f, "ultra_levels {}", settings.ultra_levels)?;
+        writeln!(f, "dig_lines {}", settings.dig_lines)?;
+        writeln!(f, "dig_rows {}", settings.dig_rows)?;
+        writeln!(f, "zen_top_out {}", settings.zen_top_out.name())?;
+        writeln!(f, "zen_clear_rows {}", settings.zen_clear_rows)?;
+        for frame in self.frames.iter() {
+            write!(f, "{}", frame.elapsed.as_micros())?;
+            for input in frame.inputs.iter() {
+                let name = match input {
+                    Input::Action(action) => action.name(),
+                    Input::Gravity => "Gravity",
+                };
+                write!(f, " {}", name)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Replay {
+    type Err = ReplayError;
+
+    fn from_str(text: &str) -> Result<Replay, ReplayError> {
+        let mut lines = text.lines().enumerate();
+
+        // The header must come first, and must be a version we understand
+        let header = lines.next().map(|(_index, line)| line).unwrap_or_default();
+        let version: u32 = match header.split_whitespace().collect::<Vec<_>>()[..] {
+            [HEADER, version] => version.parse().map_err(|_| ReplayError::Version(version.to_string()))?,
+            _ => return Err(ReplayError::Line(1, "not a replay file".to_string())),
+        };
+        if version != VERSION {
+            return Err(ReplayError::Version(version.to_string()));
+        }
+
+        let mut replay = Replay::new(&Settings::default(), 0);
+        let mut seed = None;
+        for (index, line) in lines {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let error = |message: &str| ReplayError::Line(index + 1, format!("{} in '{}'", message, line));
+            let number = |word: &str| word.parse::<u64>().map_err(|_| error("expected a number"));
+            let size = |word: &str| i32::try_from(number(word)?).map_err(|_| error("number too big"));
+            let count = |word: &str| usize::try_from(number(word)?).map_err(|_| error("number too big"));
+            match words[..] {
+                [] => {} // blank lines don't matter
+                ["seed", value] => seed = Some(number(value)?),
+                ["width", value] => replay.settings.width = size(value)?,
+                ["height", value] => replay.settings.height = size(value)?,
+                ["start", x, y] => replay.settings.start_pos = (size(x)?, size(y)?),
+                ["max_level", value] => replay.settings.max_level = count(value)?,
+                ["preview", value] => replay.settings.preview = count(value)?,
+                ["randomizer", name] => {
+                    replay.settings.randomizer = RandomizerKind::from_name(name).ok_or_else(|| error("unknown randomizer"))?
+                }
+                ["lock_delay", value] => replay.settings.lock_delay = Duration::from_nanos(number(value)?),
+                ["lock_resets", value] => replay.settings.lock_resets = count(value)?,
+                ["mode", name] => replay.settings.mode = Mode::from_name(name).ok_or_else(|| error("unknown mode"))?,
+                ["sprint_lines", value] => replay.settings.sprint_lines = count(value)?,
+                ["ultra_time", value] => replay.settings.ultra_time = Duration::from_nanos(number(value)?),
+                ["ultra_levels", value] => {
+                    replay.settings.ultra_levels = value.parse().map_err(|_| error("expected true or false"))?
+                }
+                ["dig_lines", value] => replay.settings.dig_lines = count(value)?,
+                ["dig_rows", value] => replay.settings.dig_rows = count(value)?,
+                ["zen_top_out", name] => {
+                    replay.settings.zen_top_out = TopOut::from_name(name).ok_or_else(|| error("unknown top out"))?
+                }
+                ["zen_clear_rows", value] => replay.settings.zen_clear_rows = count(value)?,
+                [elapsed, ref names @ ..] => {
+                    let mut inputs = Vec::new();
+                    for &name in names {
+                        inputs.push(match name {
+                            "Gravity" => Input::Gravity,
+                            name => Input::Action(Action::from_name(name).ok_or_else(|| error("unknown input"))?),
+                        });
+                    }
+                    replay.frames.push(Frame {
+                        elapsed: Duration::from_micros(number(elapsed)?),
+                        inputs,
+                    });
+                }
+            }
+        }
+
+        replay.settings.seed = Some(seed.ok_or_else(|| ReplayError::Line(1, "no seed".to_string()))?);
+        // The same rules as a game from the configuration, so a changed or broken file can't crash the game
+        if let Err(invalid) = replay.settings.validate() {
+            return Err(ReplayError::Line(1, format!("{} should be {}", invalid.name, invalid.expected)));
+        }
+        Ok(replay)
+    }
+}
+
+/// Feeds a replay back one frame at a time, in place of the player
+#[derive(Debug)]
+pub struct Player {
+    replay: Replay,
+    frame: usize, // the next frame to play
+}
+
+impl Player {
+    pub fn new(replay: Replay) -> Player {
+        Player { replay, frame: 0 }
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    /// Go back to the first frame
+    pub fn rewind(&mut self) {
+        self.frame = 0;
+    }
+
+    /// Have all the recorded frames been played?
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.replay.frames.len()
+    }
+
+    /// The next frame, or None when the recording is over
+    pub fn next_frame(&mut self) -> Option<Frame> {
+        let frame = self.replay.frames.get(self.frame)?.clone();
+        self.frame += 1;
+        Some(frame)
+    }
+}
+
+/// Why a replay couldn't be read
+#[derive(Debug)]
+pub enum ReplayError {
+    /// The file couldn't be read at all
+    Io(io::Error),
+    /// The file is from a version of the format we don't understand
+    Version(String),
+    /// Something is wrong on this line (counting from 1)
+    Line(usize, String),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Io(error) => write!(f, "{}", error),
+            ReplayError::Version(version) => {
+                write!(f, "replay format version {} is not supported (expected {})", version, VERSION)
+            }
+            ReplayError::Line(line, message) => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<io::Error> for ReplayError {
+    fn from(error: io::Error) -> ReplayError {
+        ReplayError::Io(error)
+    }
+}
@@ -0,0 +1,270 @@
+//! The high score table: the best games, kept in a file between runs.
+//!
+//! The file is TOML, best first, with one table per game:
+//! ```toml
+//! [[scores]]
+//! name = "Alexey"
+//! score = 48200
+//! level = 7
+//! lines = 63
+//! pieces = 171
+//! duration_ms = 412816
+//! mode = "Marathon"
+//! date = "2022-05-02"
+//! ```
+//! Only the best `size` games are kept. A file that can't be read is reported rather than trusted, and the
+//! table starts again empty.
+//!
+//! Each mode has a table of its own, as what makes a game better depends on the mode: the score in a Marathon,
+//! the time in a Sprint or a Dig (whose entries also keep their split times, `splits_ms`, to race against).
+
+use crate::game::{Game, Settings};
+use crate::mode::Mode;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// One game in the table
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Entry {
+    /// Whoever played it, as they typed it in
+    pub name: String,
+    pub score: usize,
+    pub level: usize,
+    pub lines: usize,
+    #[serde(default)]
+    pub pieces: usize,
+    /// How long the game went on for, in game time
+    pub duration_ms: u64,
+    /// The game time every `Settings::SPLIT_LINES` lines
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub splits_ms: Vec<u64>,
+    /// Which kind of game it was, with its goal, eg "Marathon" or "Sprint 40" (see `mode_title`)
+    pub mode: String,
+    /// The day it was played, as year-month-day
+    pub date: String,
+}
+
+impl Entry {
+    /// The entry for a game that has ended, without a name yet.
+    /// None if the game doesn't count: a race against the clock has to be finished to have a time, and some modes
+    /// don't keep scores at all
+    pub fn for_game(game: &Game, date: String) -> Option<Entry> {
+        let settings = game.settings();
+        if !settings.mode.keeps_scores() || (settings.mode.ranking() == Ranking::Time && !game.is_finished()) {
+            return None;
+        }
+        Some(Entry {
+            name: String::new(),
+            score: game.score(),
+            level: game.master_level().unwrap_or_else(|| game.level()), // the level a Master counts is what it is about
+            lines: game.stats().lines,
+            pieces: game.stats().pieces,
+            duration_ms: game.time().as_millis() as u64,
+            splits_ms: game.splits().iter().map(|split| split.as_millis() as u64).collect(),
+            mode: mode_title(settings),
+            date,
+        })
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.duration_ms)
+    }
+
+    pub fn splits(&self) -> Vec<Duration> {
+        self.splits_ms.iter().map(|&split| Duration::from_millis(split)).collect()
+    }
+
+    /// Tetrominoes locked per second of the game
+    pub fn pieces_per_second(&self) -> f32 {
+        if self.duration_ms == 0 {
+            return 0.0;
+        }
+        self.pieces as f32 * 1000.0 / self.duration_ms as f32
+    }
+}
+
+/// What a game is called in the tables: the mode, and its goal if it has one, eg "Sprint 40", "Ultra 2:00" or "Dig 18".
+/// An Ultra with levels going up is "Ultra 2:00 levels".
+/// Games with different goals don't go against each other for personal bests
+pub fn mode_title(settings: &Settings) -> String {
+    match settings.mode {
+        Mode::Marathon => settings.mode.name().to_string(),
+        Mode::Sprint => format!("{} {}", settings.mode.name(), settings.sprint_lines),
+        Mode::Ultra => {
+            let seconds = settings.ultra_time.as_secs();
+            let levels = if settings.ultra_levels { " levels" } else { "" };
+            format!("{} {}:{:02}{}", settings.mode.name(), seconds / 60, seconds % 60, levels)
+        }
+        Mode::Dig => format!("{} {}", settings.mode.name(), settings.dig_lines),
+        Mode::Zen | Mode::Master => settings.mode.name().to_string(),
+    }
+}
+
+/// What makes one game better than another
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Ranking {
+    /// More points
+    Score,
+    /// Less time
+    Time,
+}
+
+impl Ranking {
+    /// Is this game better than the other one? Ties go to the game that was there first
+    fn better(self, entry: &Entry, other: &Entry) -> bool {
+        match self {
+            Ranking::Score => entry.score > other.score,
+            Ranking::Time => entry.duration_ms < other.duration_ms,
+        }
+    }
+
+    /// Does this game have anything to go in the table? Nothing goes in for no score at all
+    fn counts(self, entry: &Entry) -> bool {
+        match self {
+            Ranking::Score => entry.score > 0,
+            Ranking::Time => entry.duration_ms > 0,
+        }
+    }
+}
+
+/// The best games so far, best first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HighScores {
+    entries: Vec<Entry>,
+    size: usize, // how many are kept
+    ranking: Ranking,
+}
+
+impl HighScores {
+    /// How many games are kept, unless we are told otherwise
+    pub const SIZE: usize = 10;
+
+    /// An empty table, keeping this many games ranked this way
+    pub fn new(size: usize, ranking: Ranking) -> HighScores {
+        HighScores {
+            entries: Vec::new(),
+            size,
+            ranking,
+        }
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn ranking(&self) -> Ranking {
+        self.ranking
+    }
+
+    /// The best game in the table with this title (eg "Sprint 40"), to race against
+    pub fn best(&self, title: &str) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.mode == title)
+    }
+
+    /// Where this game would go in the table (0 = the top), or None if it isn't good enough.
+    /// A game has to beat the ones already there to go above them
+    pub fn rank(&self, entry: &Entry) -> Option<usize> {
+        let rank = self
+            .entries
+            .iter()
+            .position(|other| self.ranking.better(entry, other))
+            .unwrap_or(self.entries.len());
+        Some(rank).filter(|&rank| rank < self.size && self.ranking.counts(entry))
+    }
+
+    /// Put a game in the table if it is good enough, dropping the worst if the table is full. Returns where it went
+    pub fn insert(&mut self, entry: Entry) -> Option<usize> {
+        let rank = self.rank(&entry)?;
+        self.entries.insert(rank, entry);
+        self.entries.truncate(self.size);
+        Some(rank)
+    }
+
+    /// Read the table from a file, keeping this many games ranked this way
+    pub fn load(path: &Path, size: usize, ranking: Ranking) -> Result<HighScores, ScoresError> {
+        HighScores::parse(&fs::read_to_string(path)?, size, ranking)
+    }
+
+    /// Read the table from the text of a file, keeping this many games ranked this way
+    pub fn parse(text: &str, size: usize, ranking: Ranking) -> Result<HighScores, ScoresError> {
+        let file: ScoresFile = toml::from_str(text).map_err(|error| ScoresError::Parse(error.to_string()))?;
+        // Whatever order the file has them in, and however many there are, the table is the best of them
+        let mut scores = HighScores::new(size, ranking);
+        for entry in file.scores {
+            scores.insert(entry);
+        }
+        Ok(scores)
+    }
+
+    /// Write the table to a file
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+}
+
+/// The layout of the file
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScoresFile {
+    #[serde(default)]
+    scores: Vec<Entry>,
+}
+
+impl fmt::Display for HighScores {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let file = ScoresFile {
+            scores: self.entries.clone(),
+        };
+        let text = toml::to_string(&file).map_err(|_error| fmt::Error)?;
+        write!(f, "{}", text)
+    }
+}
+
+/// Why a high score file couldn't be used
+#[derive(Debug)]
+pub enum ScoresError {
+    /// The file couldn't be read at all
+    Io(io::Error),
+    /// The file isn't TOML, or isn't laid out as a high score file (the message says where)
+    Parse(String),
+}
+
+impl fmt::Display for ScoresError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScoresError::Io(error) => write!(f, "{}", error),
+            ScoresError::Parse(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ScoresError {}
+
+impl From<io::Error> for ScoresError {
+    fn from(error: io::Error) -> ScoresError {
+        ScoresError::Io(error)
+    }
+}
+
+/// The day of a time as year-month-day (UTC), eg "2022-05-02"
+pub fn date(time: SystemTime) -> String {
+    let days = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() / 86_400;
+
+    // Count in 400 year eras starting on 1 March 2000, so the leap day is the last day of a year
+    let days = days as i64 - 11_017;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153; // from March
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = 2000 + era * 400 + year_of_era + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
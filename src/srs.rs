@@ -0,0 +1,91 @@
+//! The Super Rotation System (SRS).
+//!
+//! Each tetromino has four rotation states. When a rotation would collide with something, up to four
+//! alternative positions (the 'kicks') are tried in order, so pieces can kick off walls, the floor and the heap.
+//! See <https://tetris.fandom.com/wiki/SRS>
+//!
+//! The kick tables here are written exactly as they are usually published, with +y meaning UP.
+//! Our playing grid has +y meaning down, so the game flips the y value when it applies a kick.
+
+use crate::tetromino::TetrominoType;
+
+/// The four rotation states of a tetromino, in clockwise order
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Rotation {
+    /// '0' - the spawn state
+    Zero = 0,
+    /// 'R' - one clockwise rotation from spawn
+    Right = 1,
+    /// '2' - two rotations from spawn
+    Two = 2,
+    /// 'L' - one anti-clockwise rotation from spawn
+    Left = 3,
+}
+
+impl Rotation {
+    /// Every state, in clockwise order
+    pub const ALL: [Rotation; 4] = [Rotation::Zero, Rotation::Right, Rotation::Two, Rotation::Left];
+
+    /// The state after one clockwise rotation
+    pub fn clockwise(self) -> Rotation {
+        Rotation::ALL[(self as usize + 1) % 4]
+    }
+
+    /// The state after one anti-clockwise rotation
+    pub fn anti_clockwise(self) -> Rotation {
+        Rotation::ALL[(self as usize + 3) % 4]
+    }
+}
+
+/// Kick offsets (x, y with +y UP) for J, L, S, T and Z, one row per rotation in `TRANSITIONS` order
+#[rustfmt::skip]
+pub const JLSTZ_KICKS: [[(i32, i32); 5]; 8] = [
+    [(0, 0), (-1, 0), (-1,  1), (0, -2), (-1, -2)], // 0 -> R
+    [(0, 0), ( 1, 0), ( 1, -1), (0,  2), ( 1,  2)], // R -> 0
+    [(0, 0), ( 1, 0), ( 1, -1), (0,  2), ( 1,  2)], // R -> 2
+    [(0, 0), (-1, 0), (-1,  1), (0, -2), (-1, -2)], // 2 -> R
+    [(0, 0), ( 1, 0), ( 1,  1), (0, -2), ( 1, -2)], // 2 -> L
+    [(0, 0), (-1, 0), (-1, -1), (0,  2), (-1,  2)], // L -> 2
+    [(0, 0), (-1, 0), (-1, -1), (0,  2), (-1,  2)], // L -> 0
+    [(0, 0), ( 1, 0), ( 1,  1), (0, -2), ( 1, -2)], // 0 -> L
+];
+
+/// Kick offsets (x, y with +y UP) for I, one row per rotation in `TRANSITIONS` order
+#[rustfmt::skip]
+pub const I_KICKS: [[(i32, i32); 5]; 8] = [
+    [(0, 0), (-2, 0), ( 1, 0), (-2, -1), ( 1,  2)], // 0 -> R
+    [(0, 0), ( 2, 0), (-1, 0), ( 2,  1), (-1, -2)], // R -> 0
+    [(0, 0), (-1, 0), ( 2, 0), (-1,  2), ( 2, -1)], // R -> 2
+    [(0, 0), ( 1, 0), (-2, 0), ( 1, -2), (-2,  1)], // 2 -> R
+    [(0, 0), ( 2, 0), (-1, 0), ( 2,  1), (-1, -2)], // 2 -> L
+    [(0, 0), (-2, 0), ( 1, 0), (-2, -1), ( 1,  2)], // L -> 2
+    [(0, 0), ( 1, 0), (-2, 0), ( 1, -2), (-2,  1)], // L -> 0
+    [(0, 0), (-1, 0), ( 2, 0), (-1,  2), ( 2, -1)], // 0 -> L
+];
+
+/// O doesn't kick (its rotations all look the same)
+pub const O_KICKS: [(i32, i32); 5] = [(0, 0); 5];
+
+/// The rows of the kick tables
+pub const TRANSITIONS: [(Rotation, Rotation); 8] = [
+    (Rotation::Zero, Rotation::Right),
+    (Rotation::Right, Rotation::Zero),
+    (Rotation::Right, Rotation::Two),
+    (Rotation::Two, Rotation::Right),
+    (Rotation::Two, Rotation::Left),
+    (Rotation::Left, Rotation::Two),
+    (Rotation::Left, Rotation::Zero),
+    (Rotation::Zero, Rotation::Left),
+];
+
+/// The kicks to try, in order, when rotating a tetromino between two adjacent states (x, y with +y UP).
+///
+/// Returns None if the states are not one rotation apart.
+pub fn kicks(tetromino_type: TetrominoType, from: Rotation, to: Rotation) -> Option<[(i32, i32); 5]> {
+    let row = TRANSITIONS.iter().position(|&transition| transition == (from, to))?;
+    Some(match tetromino_type {
+        TetrominoType::I => I_KICKS[row],
+        TetrominoType::O => O_KICKS,
+        _ => JLSTZ_KICKS[row],
+    })
+}
@@ -0,0 +1,135 @@
+use crate::srs::Rotation;
+use rand::{
+    distributions::{Distribution, Standard},
+    Rng,
+};
+
+/// The different types of tetromino we can have
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TetrominoType {
+    I = 0,
+    O = 1,
+    T = 2,
+    S = 3,
+    Z = 4,
+    L = 5,
+    J = 6,
+}
+
+/// The blocks within each type of tetromino
+/// Initial presentation is 'flat side down' as per guidelines
+impl TetrominoType {
+    /// Every type, in index order
+    pub const ALL: [TetrominoType; 7] = [
+        TetrominoType::I,
+        TetrominoType::O,
+        TetrominoType::T,
+        TetrominoType::S,
+        TetrominoType::Z,
+        TetrominoType::L,
+        TetrominoType::J,
+    ];
+
+    /// Block positions within the bounding box, indexed by type
+    /// These are placed as SRS expects, so that rotating them about the centre of the box gives the other states
+    pub const BLOCK_INDICES: [[(i32, i32); 4]; 7] = [
+        [
+            // line, cyan
+            (3, 1),
+            (2, 1),
+            (1, 1),
+            (0, 1),
+        ],
+        [
+            // square, yellow
+            (0, 0),
+            (0, 1),
+            (1, 0),
+            (1, 1),
+        ],
+        [
+            // T, purple
+            (0, 1),
+            (1, 1),
+            (2, 1),
+            (1, 0),
+        ],
+        [
+            // Z, red
+            (0, 1),
+            (1, 1),
+            (1, 0),
+            (2, 0),
+        ],
+        [
+            // S, green
+            (2, 1),
+            (1, 1),
+            (1, 0),
+            (0, 0),
+        ],
+        [
+            // L, blue
+            (2, 0),
+            (0, 1),
+            (1, 1),
+            (2, 1),
+        ],
+        [
+            // J, orange *
+            (0, 1),
+            (1, 1),
+            (2, 1),
+            (0, 0),
+        ],
+    ];
+
+    /// The size of the bounding box
+    pub const SIZES: [i32; 7] = [
+        4, // line, cyan
+        2, // square, yellow
+        3, // T, purple
+        3, // Z, red
+        3, // S, green
+        3, // L, blue
+        3, // J, orange
+    ];
+
+    /// The block positions of this type within its bounding box, in the spawn state
+    pub fn blocks(self) -> [(i32, i32); 4] {
+        TetrominoType::BLOCK_INDICES[self as usize]
+    }
+
+    /// The block positions of this type within its bounding box, in any rotation state
+    pub fn blocks_in(self, rotation: Rotation) -> [(i32, i32); 4] {
+        let last = self.size() - 1;
+        let mut blocks = self.blocks();
+        for _turn in 0..rotation as usize {
+            // A quarter turn clockwise about the centre of the box (remember +y is down)
+            for block in blocks.iter_mut() {
+                *block = (last - block.1, block.0);
+            }
+        }
+        blocks
+    }
+
+    /// The size of the (square) bounding box of this type
+    pub fn size(self) -> i32 {
+        TetrominoType::SIZES[self as usize]
+    }
+}
+
+/// Random distribution of the types
+impl Distribution<TetrominoType> for Standard {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> TetrominoType {
+        match rng.gen_range(0, 7) {
+            0 => TetrominoType::I,
+            1 => TetrominoType::O,
+            2 => TetrominoType::T,
+            3 => TetrominoType::S,
+            4 => TetrominoType::Z,
+            5 => TetrominoType::L,
+            _ => TetrominoType::J,
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use std::time::Duration;
+use tetris::autorepeat::AutoRepeat;
+
+/// Hold a key for a second at this frame rate, returning how many moves it made
+fn hold_for_a_second(key: &mut AutoRepeat, fps: u32) -> usize {
+    let frame = Duration::from_secs(1) / fps;
+    (0..fps).map(|_frame| key.update(true, frame)).sum()
+}
+
+#[test]
+fn waits_then_repeats() {
+    let mut key = AutoRepeat::new(Duration::from_millis(150), Duration::from_millis(50));
+
+    // Once on the press, then nothing until the delay is up
+    assert_eq!(key.update(true, Duration::from_millis(10)), 1);
+    assert_eq!(key.update(true, Duration::from_millis(100)), 0);
+    assert_eq!(key.update(true, Duration::from_millis(49)), 0);
+    assert_eq!(key.update(true, Duration::from_millis(1)), 1);
+
+    // Then every interval, catching up after a slow frame
+    assert_eq!(key.update(true, Duration::from_millis(40)), 0);
+    assert_eq!(key.update(true, Duration::from_millis(10)), 1);
+    assert_eq!(key.update(true, Duration::from_millis(120)), 2);
+
+    // Letting go starts all over again
+    assert_eq!(key.update(false, Duration::from_millis(10)), 0);
+    assert_eq!(key.update(true, Duration::from_millis(10)), 1);
+    assert_eq!(key.update(true, Duration::from_millis(100)), 0);
+}
+
+#[test]
+fn frame_rate_doesnt_matter() {
+    let mut key = AutoRepeat::new(Duration::from_millis(167), Duration::from_millis(33));
+
+    // 1 for the press and (1000 - 167) / 33 + 1 repeats, less any not quite due at the end of the last frame
+    let moves: Vec<usize> = [30, 60, 144, 240]
+        .iter()
+        .map(|&fps| {
+            key.update(false, Duration::ZERO);
+            hold_for_a_second(&mut key, fps)
+        })
+        .collect();
+    for count in moves.iter() {
+        assert!((25..=27).contains(count), "{:?}", moves);
+    }
+}
+
+#[test]
+fn zero_interval_goes_all_the_way() {
+    let mut key = AutoRepeat::new(Duration::from_millis(100), Duration::ZERO);
+    assert_eq!(key.update(true, Duration::from_millis(16)), 1);
+    assert_eq!(key.update(true, Duration::from_millis(50)), 0);
+    assert!(key.update(true, Duration::from_millis(50)) > 100);
+    assert_eq!(key.update(true, Duration::from_millis(50)), 0);
+}
@@ -0,0 +1,85 @@
+use tetris::bindings::{Bindings, BindingsError, Control};
+use tetris::game::Action;
+
+#[test]
+fn defaults_are_the_keys_the_game_always_had() {
+    let bindings = Bindings::default();
+    assert_eq!(bindings.keys(Control::Play(Action::MoveLeft)), ["J", "Left"]);
+    assert_eq!(bindings.keys(Control::Pause), ["P", "Escape"]);
+    assert_eq!(bindings.control("Space"), Some(Control::Play(Action::HardDrop)));
+    assert_eq!(bindings.control("F1"), None);
+    assert!(bindings.conflicts().is_empty());
+
+    // Every control has a name that finds it again
+    for control in Control::ALL {
+        assert_eq!(Control::from_name(control.name()), Some(control));
+    }
+}
+
+#[test]
+fn saved_bindings_read_back_the_same() {
+    let mut bindings = Bindings::default();
+    bindings.clear(Control::Play(Action::HardDrop));
+    bindings.bind(Control::Play(Action::HardDrop), "W").unwrap();
+    bindings.bind(Control::Ghost, "F1").unwrap();
+
+    let text = bindings.to_string();
+    assert!(text.starts_with("[keys]\nMoveLeft = [\"J\", \"Left\"]\n"));
+    assert_eq!(text.parse::<Bindings>().unwrap(), bindings);
+
+    // Controls a file doesn't mention keep their defaults
+    let bindings: Bindings = "[keys]\nHold = [\"LShift\"]\n".parse().unwrap();
+    assert_eq!(bindings.keys(Control::Play(Action::Hold)), ["LShift"]);
+    assert_eq!(bindings.keys(Control::Quit), ["Q"]);
+}
+
+#[test]
+fn a_key_only_does_one_thing() {
+    // Binding a key that is already used says what it is used for, and changes nothing
+    let mut bindings = Bindings::default();
+    assert_eq!(bindings.bind(Control::Play(Action::Hold), "Space"), Err(Control::Play(Action::HardDrop)));
+    assert_eq!(bindings, Bindings::default());
+    assert_eq!(bindings.bind(Control::Play(Action::HardDrop), "Space"), Ok(()));
+    assert_eq!(bindings, Bindings::default());
+
+    // A file that binds a key twice is refused
+    let error = "[keys]\nHold = [\"Space\"]\n".parse::<Bindings>().unwrap_err();
+    assert!(matches!(error, BindingsError::Conflict(_, _, _)));
+    assert_eq!(error.to_string(), "Space is bound to both HardDrop and Hold");
+
+    // As are controls we don't have, and keys the view doesn't know
+    let error = "[keys]\nTeleport = [\"T\"]\n".parse::<Bindings>().unwrap_err();
+    assert_eq!(error.to_string(), "there is no control called 'Teleport'");
+    let bindings: Bindings = "[keys]\nQuit = [\"Nope\"]\n".parse().unwrap();
+    let error = bindings.check(|key| key != "Nope", |_button| true).unwrap_err();
+    assert_eq!(error.to_string(), "unknown key 'Nope' for Quit");
+}
+
+#[test]
+fn gamepad_buttons_are_bound_like_keys() {
+    let mut bindings = Bindings::default();
+    assert_eq!(bindings.buttons(Control::Play(Action::MoveLeft)), ["DPadLeft"]);
+    assert_eq!(bindings.button_control("LeftTrigger"), Some(Control::Play(Action::Hold)));
+    assert_eq!(bindings.button_control("North"), None);
+
+    // Buttons and keys are kept apart, so a button can share a name with a key that does something else
+    assert_eq!(bindings.control("C"), Some(Control::Play(Action::Hold)));
+    assert_eq!(bindings.bind_button(Control::Ghost, "C"), Ok(()));
+    assert_eq!(bindings.bind_button(Control::Quit, "C"), Err(Control::Ghost));
+    assert_eq!(bindings.bind_button(Control::Quit, "Start"), Err(Control::Pause));
+
+    // They are saved in their own section, and read back the same
+    let text = bindings.to_string();
+    assert!(text.contains("\n[buttons]\nMoveLeft = [\"DPadLeft\"]\n"));
+    assert_eq!(text.parse::<Bindings>().unwrap(), bindings);
+
+    // Clearing a control takes its buttons off too
+    bindings.clear(Control::Play(Action::Hold));
+    assert!(bindings.buttons(Control::Play(Action::Hold)).is_empty());
+
+    // A button bound twice, or one the view doesn't know, is refused
+    let error = "[buttons]\nGhost = [\"South\"]\n".parse::<Bindings>().unwrap_err();
+    assert_eq!(error.to_string(), "South is bound to both RotateClockwise and Ghost");
+    let error = Bindings::default().check(|_key| true, |button| button != "Start").unwrap_err();
+    assert!(matches!(error, BindingsError::UnknownButton(_, Control::Pause)));
+}
@@ -0,0 +1,62 @@
+use std::path::Path;
+use tetris::config::{ConfigError, GameConfig};
+use tetris::randomizer::RandomizerKind;
+
+#[test]
+fn missing_values_keep_their_defaults() {
+    let config: GameConfig = "field_height = 24\nrandomizer = \"tgm\"\nborder_color = [1.0, 0.5, 0.0, 0.6]\n"
+        .parse()
+        .unwrap();
+    assert_eq!(config.field_height, 24);
+    assert_eq!(config.border_color, [1.0, 0.5, 0.0, 0.6]);
+    assert_eq!(
+        config,
+        GameConfig {
+            field_height: 24,
+            randomizer: "tgm".to_string(),
+            border_color: [1.0, 0.5, 0.0, 0.6],
+            ..GameConfig::default()
+        }
+    );
+
+    let settings = config.settings();
+    assert_eq!((settings.width, settings.height), (10, 24));
+    assert_eq!(settings.randomizer, RandomizerKind::Tgm);
+
+    // An empty file is all defaults, and the defaults can be written out and read back
+    assert_eq!("".parse::<GameConfig>().unwrap(), GameConfig::default());
+    assert_eq!(GameConfig::default().to_toml().parse::<GameConfig>().unwrap(), GameConfig::default());
+}
+
+#[test]
+fn bad_values_say_what_is_wrong() {
+    let error = |text: &str| text.parse::<GameConfig>().unwrap_err();
+
+    // Out of range values name the setting
+    match error("field_width = 2") {
+        ConfigError::Invalid(name, _expected) => assert_eq!(name, "field_width"),
+        other => panic!("expected an invalid value, got {:?}", other),
+    }
+    assert!(error("randomizer = \"fair\"").to_string().starts_with("randomizer should be one of"));
+    assert!(error("ghost_alpha = 1.5").to_string().contains("ghost_alpha"));
+    assert!(error("field_color = [0.2, 0.2, 2.0, 0.5]").to_string().contains("field_color"));
+    assert!(error("field_height = 8\ndig_rows = 9").to_string().starts_with("dig_rows"));
+    assert!(error("zen_top_out = \"end\"").to_string().starts_with("zen_top_out should be clear or reset"));
+    assert!(error("lock_delay = inf").to_string().starts_with("lock_delay"));
+    assert!(error("drop_speed_factor = 1e30").to_string().starts_with("drop_speed_factor"));
+
+    // So do typos and the wrong type of value, with where they are in the file
+    assert!(matches!(error("feild_width = 12"), ConfigError::Parse(message) if message.contains("feild_width")));
+    assert!(matches!(error("\nblock_size = \"big\""), ConfigError::Parse(message) if message.contains("line 2")));
+    assert!(matches!(error("field_width = "), ConfigError::Parse(_)));
+}
+
+#[test]
+fn the_example_file_is_valid() {
+    let config = GameConfig::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tetris.example.toml")).unwrap();
+    assert_eq!(config.settings().width, GameConfig::default().field_width);
+    assert!(matches!(
+        GameConfig::load(Path::new("no such file.toml")),
+        Err(ConfigError::Io(_))
+    ));
+}
@@ -0,0 +1,42 @@
+use std::time::Duration;
+use tetris::master::{self, ONE_G, TWENTY_G};
+
+#[test]
+fn gravity_climbs_to_20g() {
+    assert_eq!(master::gravity(0), 4);
+    assert_eq!(master::gravity(29), 4);
+    assert_eq!(master::gravity(30), 6);
+    assert_eq!(master::gravity(251), ONE_G);
+    assert_eq!(master::gravity(499), 3 * ONE_G);
+    assert_eq!(master::gravity(500), TWENTY_G);
+    assert_eq!(master::gravity(999), TWENTY_G);
+
+    // Level 200 starts slow again
+    assert!(master::gravity(200) < master::gravity(199));
+}
+
+#[test]
+fn the_time_gets_shorter_at_the_top() {
+    let frames = |frames: u64| Duration::from_micros(frames * 1_000_000 / 60);
+    assert_eq!(master::entry_delay(0), frames(25));
+    assert_eq!(master::entry_delay(699), frames(25));
+    assert_eq!(master::entry_delay(800), frames(12));
+    assert_eq!(master::lock_delay(899), frames(30));
+    assert_eq!(master::lock_delay(900), frames(17));
+}
+
+#[test]
+fn sections_stop_at_x99_then_998() {
+    assert_eq!(master::section_stop(0), 99);
+    assert_eq!(master::section_stop(99), 99);
+    assert_eq!(master::section_stop(100), 199);
+    assert_eq!(master::section_stop(950), 998);
+}
+
+#[test]
+fn a_fall_adds_up_to_rows() {
+    // 1G is a row every frame, 20G twenty of them
+    assert_eq!(master::fall(ONE_G, Duration::from_secs(1)), 60 * master::ROW);
+    assert_eq!(master::fall(TWENTY_G, Duration::from_micros(16_667)) / master::ROW, 20);
+    assert!(master::fall(4, Duration::from_micros(16_667)) < master::ROW);
+}
@@ -0,0 +1,100 @@
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use tetris::randomizer::RandomizerKind;
+use tetris::tetromino::TetrominoType;
+
+/// Draw a long sequence from a randomizer with a fixed seed
+fn sequence(kind: RandomizerKind, length: usize) -> Vec<TetrominoType> {
+    let mut rng = StdRng::seed_from_u64(20220419);
+    let mut randomizer = kind.create();
+    (0..length).map(|_| randomizer.next(&mut rng)).collect()
+}
+
+/// How many of each type in a sequence
+fn counts(sequence: &[TetrominoType]) -> [usize; 7] {
+    let mut counts = [0; 7];
+    for &tetromino_type in sequence {
+        counts[tetromino_type as usize] += 1;
+    }
+    counts
+}
+
+/// The longest run of tetrominoes without one of the given type
+fn longest_drought(sequence: &[TetrominoType], tetromino_type: TetrominoType) -> usize {
+    sequence
+        .split(|&t| t == tetromino_type)
+        .map(|run| run.len())
+        .max()
+        .unwrap_or(0)
+}
+
+#[test]
+fn bag7_deals_every_type_once_per_bag() {
+    let sequence = sequence(RandomizerKind::Bag7, 7 * 1000);
+    for bag in sequence.chunks(7) {
+        assert_eq!(counts(bag), [1; 7]);
+    }
+
+    // So you never wait more than 12 for any tetromino
+    for tetromino_type in TetrominoType::ALL {
+        assert!(longest_drought(&sequence, tetromino_type) <= 12);
+    }
+}
+
+#[test]
+fn bag14_deals_every_type_twice_per_bag() {
+    let sequence = sequence(RandomizerKind::Bag14, 14 * 1000);
+    for bag in sequence.chunks(14) {
+        assert_eq!(counts(bag), [2; 7]);
+    }
+
+    for tetromino_type in TetrominoType::ALL {
+        assert!(longest_drought(&sequence, tetromino_type) <= 24);
+    }
+}
+
+#[test]
+fn random_is_uniform_but_allows_droughts() {
+    let length = 70_000;
+    let sequence = sequence(RandomizerKind::Random, length);
+
+    // Chi-squared with 6 degrees of freedom, 16.81 is the 1% critical value
+    let expected = length as f64 / 7.0;
+    let chi_squared: f64 = counts(&sequence)
+        .iter()
+        .map(|&count| (count as f64 - expected).powi(2) / expected)
+        .sum();
+    assert!(chi_squared < 16.81, "chi squared {}", chi_squared);
+
+    // With this many tetrominoes, pure random will have a gap the bag never allows
+    assert!(TetrominoType::ALL
+        .iter()
+        .any(|&tetromino_type| longest_drought(&sequence, tetromino_type) > 12));
+}
+
+#[test]
+fn tgm_history_starts_safely_and_avoids_repeats() {
+    // The first tetromino never leaves an overhang
+    for seed in 0..200 {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let first = RandomizerKind::Tgm.create().next(&mut rng);
+        assert!(![TetrominoType::S, TetrominoType::Z, TetrominoType::O].contains(&first));
+    }
+
+    let length = 70_000;
+    let sequence = sequence(RandomizerKind::Tgm, length);
+
+    // Something from the last four only gets through when every roll hits the history, about (4/7)^6 = 3.5%
+    let repeats = sequence
+        .windows(5)
+        .filter(|window| window[..4].contains(&window[4]))
+        .count();
+    let repeat_rate = repeats as f64 / length as f64;
+    assert!(repeat_rate < 0.05, "repeat rate {}", repeat_rate);
+
+    // It is still fair over a long game
+    for count in counts(&sequence) {
+        let share = count as f64 / length as f64;
+        assert!((share - 1.0 / 7.0).abs() < 0.01, "share {}", share);
+    }
+}
@@ -0,0 +1,115 @@
+use std::time::Duration;
+use tetris::config::GameConfig;
+use tetris::game::{Action, Game, Settings};
+use tetris::mode::{Mode, TopOut};
+use tetris::replay::{Input, Replay, ReplayError, VERSION};
+
+/// Play a game with a fixed pattern of inputs, recording it as we go
+fn recorded_game(seed: u64) -> (Game, Replay) {
+    let mut game = Game::new(Settings {
+        seed: Some(seed),
+        ..Settings::default()
+    });
+    game.reset();
+    let mut replay = Replay::new(game.settings(), game.seed());
+
+    for frame in 0..1500 {
+        let mut inputs = Vec::new();
+        if frame % 7 == 0 {
+            inputs.push(Input::Action(Action::ALL[frame / 7 % Action::ALL.len()]));
+        }
+        if frame % 5 == 0 {
+            inputs.push(Input::Gravity);
+        }
+        for input in inputs {
+            replay.record(input);
+            input.apply_to(&mut game);
+        }
+        // Uneven frames, as a real game would have
+        let elapsed = Replay::frame_time(Duration::from_secs_f32(0.01 + (frame % 3) as f32 * 0.004));
+        game.step(elapsed);
+        replay.next_frame(elapsed);
+        if game.is_game_over() {
+            break;
+        }
+    }
+    (game, replay)
+}
+
+#[test]
+fn replays_give_the_same_game() {
+    let (game, replay) = recorded_game(99);
+    assert!(game.score() > 0);
+
+    // Through a file and back
+    let text = replay.to_string();
+    assert!(text.starts_with(&format!("tetris-replay {}\nseed 99\n", VERSION)));
+    let loaded: Replay = text.parse().unwrap();
+    assert_eq!(loaded.frames, replay.frames);
+    assert_eq!(loaded.settings.lock_delay, replay.settings.lock_delay);
+
+    // A fresh game with a different seed ends up exactly where the recorded one did
+    let mut played = Game::new(Settings {
+        seed: Some(1),
+        ..Settings::default()
+    });
+    loaded.play(&mut played);
+    assert_eq!(played.seed(), 99);
+    assert_eq!(played.score(), game.score());
+    assert_eq!(played.board().occupation(), game.board().occupation());
+    assert_eq!(played.is_game_over(), game.is_game_over());
+}
+
+#[test]
+fn replays_keep_the_configured_lock_delay() {
+    // 0.3 seconds as an f32 isn't a whole number of milliseconds (or microseconds), but it must play back the same
+    let settings = GameConfig {
+        lock_delay: 0.3,
+        ..GameConfig::default()
+    }
+    .settings();
+    let loaded: Replay = Replay::new(&settings, 3).to_string().parse().unwrap();
+    assert_eq!(loaded.settings.lock_delay, settings.lock_delay);
+}
+
+#[test]
+fn replays_keep_the_mode() {
+    let settings = Settings {
+        mode: Mode::Ultra,
+        ultra_time: Duration::from_secs_f32(90.3), // not a whole number of milliseconds
+        ultra_levels: true,
+        zen_top_out: TopOut::Reset,
+        ..Settings::default()
+    };
+    let loaded: Replay = Replay::new(&settings, 3).to_string().parse().unwrap();
+    assert_eq!(loaded.settings.mode, Mode::Ultra);
+    assert_eq!(loaded.settings.ultra_time, settings.ultra_time);
+    assert!(loaded.settings.ultra_levels);
+    assert_eq!(loaded.settings.zen_top_out, TopOut::Reset);
+}
+
+#[test]
+fn bad_replays_are_refused() {
+    let (_game, replay) = recorded_game(5);
+    let text = replay.to_string();
+
+    let newer = text.replacen(&format!("tetris-replay {}", VERSION), "tetris-replay 999", 1);
+    assert!(matches!(newer.parse::<Replay>(), Err(ReplayError::Version(_))));
+
+    assert!(matches!("hello\n".parse::<Replay>(), Err(ReplayError::Line(1, _))));
+
+    let broken = format!("{}16667 MoveLeft Teleport\n", text);
+    match broken.parse::<Replay>() {
+        Err(ReplayError::Line(line, message)) => {
+            assert_eq!(line, text.lines().count() + 1);
+            assert!(message.contains("Teleport"));
+        }
+        other => panic!("expected a line error, got {:?}", other),
+    }
+
+    // Settings a game can't be played with are refused, as they are in the configuration
+    let no_levels = text.replacen("max_level 20\n", "max_level 0\n", 1);
+    assert!(matches!(no_levels.parse::<Replay>(), Err(ReplayError::Line(1, message)) if message.starts_with("max_level")));
+    let huge = text.replacen("width 10\n", "width 4294967306\n", 1);
+    assert!(matches!(huge.parse::<Replay>(), Err(ReplayError::Line(_, message)) if message.contains("too big")));
+}
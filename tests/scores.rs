@@ -0,0 +1,108 @@
+use std::time::{Duration, UNIX_EPOCH};
+use tetris::game::{Action, Game, Settings};
+use tetris::mode::Mode;
+use tetris::scores::{self, Entry, HighScores, Ranking, ScoresError};
+
+/// A game in the table with just a name and a score
+fn entry(name: &str, score: usize) -> Entry {
+    Entry {
+        name: name.to_string(),
+        score,
+        level: 1,
+        lines: 0,
+        pieces: 100,
+        duration_ms: 60_000,
+        splits_ms: Vec::new(),
+        mode: "Marathon".to_string(),
+        date: "2022-05-02".to_string(),
+    }
+}
+
+#[test]
+fn the_table_keeps_the_best_games_in_order() {
+    let mut table = HighScores::new(3, Ranking::Score);
+    assert_eq!(table.rank(&entry("Z", 0)), None); // no score, no place
+    assert_eq!(table.insert(entry("A", 100)), Some(0));
+    assert_eq!(table.insert(entry("B", 300)), Some(0));
+    assert_eq!(table.insert(entry("C", 200)), Some(1));
+
+    // A tie goes under the game that got there first
+    assert_eq!(table.rank(&entry("D", 200)), Some(2));
+    assert_eq!(table.insert(entry("D", 200)), Some(2));
+    let names: Vec<&str> = table.entries().iter().map(|entry| entry.name.as_str()).collect();
+    assert_eq!(names, ["B", "C", "D"]);
+
+    // A full table has no room for worse games
+    assert_eq!(table.rank(&entry("E", 150)), None);
+    assert_eq!(table.insert(entry("E", 150)), None);
+    assert_eq!(table.entries().len(), 3);
+}
+
+#[test]
+fn an_entry_comes_from_the_game() {
+    let mut game = Game::new(Settings::default());
+    game.reset_with_seed(1);
+    game.step(Duration::from_millis(1500));
+    assert!(game.apply(Action::HardDrop));
+    game.step(Duration::from_millis(250));
+
+    let entry = Entry::for_game(&game, "2022-05-02".to_string()).unwrap();
+    assert_eq!(entry.score, game.score());
+    assert_eq!((entry.level, entry.lines), (1, 0));
+    assert_eq!(entry.duration(), Duration::from_millis(1750));
+    assert_eq!(entry.mode, "Marathon");
+    assert!(entry.name.is_empty());
+}
+
+#[test]
+fn sprints_are_ranked_on_time() {
+    let sprint = |name: &str, duration_ms: u64| Entry {
+        duration_ms,
+        splits_ms: vec![duration_ms / 2],
+        mode: "Sprint 40".to_string(),
+        ..entry(name, 1000)
+    };
+    let mut table = HighScores::new(3, Ranking::Time);
+    assert_eq!(table.insert(sprint("A", 90_000)), Some(0));
+    assert_eq!(table.insert(sprint("B", 60_000)), Some(0));
+    assert_eq!(table.insert(sprint("C", 90_000)), Some(2));
+    assert_eq!(table.best("Sprint 40").unwrap().splits(), [Duration::from_secs(30)]);
+    assert!(table.best("Sprint 20").is_none());
+    assert_eq!(table.entries()[0].pieces_per_second(), 100.0 / 60.0);
+
+    // A Sprint that didn't reach its goal has no time to go in the table
+    let mut game = Game::new(Settings {
+        mode: Mode::Sprint,
+        ..Settings::default()
+    });
+    game.reset_with_seed(1);
+    game.step(Duration::from_millis(1500));
+    assert!(Entry::for_game(&game, "2022-05-02".to_string()).is_none());
+    assert_eq!(scores::mode_title(game.settings()), "Sprint 40");
+}
+
+#[test]
+fn saved_scores_read_back_the_same() {
+    let mut table = HighScores::new(HighScores::SIZE, Ranking::Score);
+    table.insert(entry("Alexey", 48200));
+    table.insert(entry("Vadim", 1200));
+    let text = table.to_string();
+    assert!(text.starts_with("[[scores]]\nname = \"Alexey\"\nscore = 48200\n"));
+    assert_eq!(HighScores::parse(&text, HighScores::SIZE, Ranking::Score).unwrap(), table);
+
+    // A file out of order, or with more games than we keep, is sorted out as it is read
+    let table = HighScores::parse(&text, 1, Ranking::Score).unwrap();
+    assert_eq!(table.entries(), [entry("Alexey", 48200)]);
+
+    // An empty file is an empty table, but one that isn't a table at all is refused
+    assert!(HighScores::parse("", 5, Ranking::Score).unwrap().entries().is_empty());
+    assert!(matches!(HighScores::parse("[[scores]]\nname = 3\n", 5, Ranking::Score), Err(ScoresError::Parse(_))));
+    assert!(matches!(HighScores::parse("\u{0}garbage", 5, Ranking::Score), Err(ScoresError::Parse(_))));
+}
+
+#[test]
+fn dates_are_year_month_day() {
+    assert_eq!(scores::date(UNIX_EPOCH), "1970-01-01");
+    assert_eq!(scores::date(UNIX_EPOCH + Duration::from_secs(951_782_400)), "2000-02-29");
+    assert_eq!(scores::date(UNIX_EPOCH + Duration::from_secs(1_651_500_000)), "2022-05-02");
+}
@@ -0,0 +1,202 @@
+use tetris::game::{Action, Clear, Event, Game, Settings, TSpin};
+use tetris::tetromino::TetrominoType;
+
+/// Spawn a tetromino, turn it clockwise, slide it so its leftmost block is in column `left` and drop it
+fn drop_piece(game: &mut Game, tetromino_type: TetrominoType, turns: usize, left: i32) {
+    game.spawn(tetromino_type);
+    for _ in 0..turns {
+        assert!(game.apply(Action::RotateClockwise));
+    }
+    slide_to(game, left);
+    assert!(game.apply(Action::HardDrop));
+}
+
+/// Move the current tetromino sideways until its leftmost block is in column `left`
+fn slide_to(game: &mut Game, left: i32) {
+    let leftmost = |game: &Game| game.piece().unwrap().blocks.iter().map(|block| block.0).min().unwrap();
+    while leftmost(game) > left {
+        assert!(game.apply(Action::MoveLeft));
+    }
+    while leftmost(game) < left {
+        assert!(game.apply(Action::MoveRight));
+    }
+}
+
+/// The Scored events since the last call
+fn scored(game: &mut Game) -> Vec<Clear> {
+    game.take_events()
+        .into_iter()
+        .filter_map(|event| match event {
+            Event::Scored(clear) => Some(clear),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn t_spin_double() {
+    let mut game = Game::new(Settings::default());
+
+    // A slot for a T pointing down, with an overhang on the right:
+    //   row 21  . . . J J J . . . .
+    //   row 22  L . . . T J I I I I
+    //   row 23  L L . T T T I I I I
+    drop_piece(&mut game, TetrominoType::L, 1, 0);
+    drop_piece(&mut game, TetrominoType::I, 0, 6);
+    drop_piece(&mut game, TetrominoType::I, 0, 6);
+    drop_piece(&mut game, TetrominoType::T, 0, 3);
+    drop_piece(&mut game, TetrominoType::J, 2, 3);
+    assert!(scored(&mut game).is_empty());
+
+    // The T comes down on its side, then turns into the slot under the overhang
+    game.spawn(TetrominoType::T);
+    assert!(game.apply(Action::RotateAntiClockwise));
+    slide_to(&mut game, 1);
+    while game.gravity() {}
+    assert!(game.apply(Action::RotateAntiClockwise));
+    assert!(game.apply(Action::HardDrop));
+
+    let clears = scored(&mut game);
+    assert_eq!(
+        clears,
+        vec![Clear {
+            lines: 2,
+            t_spin: Some(TSpin::Full),
+            combo: 0,
+            back_to_back: false,
+            perfect: false,
+            points: 1200,
+        }]
+    );
+    assert_eq!(clears[0].name(), "T-Spin Double");
+}
+
+#[test]
+fn t_spin_mini_against_the_wall() {
+    let mut game = Game::new(Settings::default());
+    drop_piece(&mut game, TetrominoType::I, 0, 1);
+
+    // A T on its side against the wall, turned out and back in again
+    game.spawn(TetrominoType::T);
+    assert!(game.apply(Action::RotateClockwise));
+    slide_to(&mut game, 0);
+    while game.gravity() {}
+    assert!(game.apply(Action::RotateAntiClockwise));
+    assert!(game.apply(Action::RotateClockwise));
+    game.take_events();
+    let score = game.score();
+    assert!(game.apply(Action::HardDrop));
+
+    // Only one of the corners it points at is filled, and no lines
+    let clears = scored(&mut game);
+    assert_eq!(clears.len(), 1);
+    assert_eq!(clears[0].t_spin, Some(TSpin::Mini));
+    assert_eq!(clears[0].name(), "T-Spin Mini");
+    assert_eq!(game.score(), score + 100);
+}
+
+#[test]
+fn moving_after_turning_is_not_a_spin() {
+    let mut game = Game::new(Settings::default());
+    drop_piece(&mut game, TetrominoType::I, 0, 1);
+
+    // The same T, but dropped into place rather than turned
+    game.spawn(TetrominoType::T);
+    assert!(game.apply(Action::RotateClockwise));
+    slide_to(&mut game, 0);
+    assert!(game.apply(Action::HardDrop));
+    assert!(scored(&mut game).is_empty());
+
+    // Plain line clears have their own names
+    let clear = Clear {
+        lines: 4,
+        t_spin: None,
+        combo: 0,
+        back_to_back: false,
+        perfect: false,
+        points: 800,
+    };
+    assert_eq!(clear.name(), "Tetris");
+}
+
+#[test]
+fn clears_in_a_row_are_a_combo() {
+    let mut game = Game::new(Settings::default());
+
+    // Four rows of O's, all but the last two columns, and one more on top so they don't clear everything
+    for _ in 0..2 {
+        for left in [0, 2, 4, 6] {
+            drop_piece(&mut game, TetrominoType::O, 0, left);
+        }
+    }
+    drop_piece(&mut game, TetrominoType::O, 0, 0);
+    assert!(scored(&mut game).is_empty());
+
+    // Each O down the side clears two of them, the second one as a combo
+    drop_piece(&mut game, TetrominoType::O, 0, 8);
+    assert_eq!(scored(&mut game)[0].points, 300);
+    assert_eq!(game.combo(), Some(0));
+    drop_piece(&mut game, TetrominoType::O, 0, 8);
+    let clears = scored(&mut game);
+    assert_eq!(clears[0].combo, 1);
+    assert_eq!(clears[0].points, 300 + 50);
+    assert_eq!(game.combo(), Some(1));
+
+    // Locking without a clear ends it
+    drop_piece(&mut game, TetrominoType::O, 0, 0);
+    assert!(scored(&mut game).is_empty());
+    assert_eq!(game.combo(), None);
+}
+
+#[test]
+fn tetris_after_tetris_is_back_to_back() {
+    let mut game = Game::new(Settings::default());
+
+    // Eight rows with a well in the last column, and a bit more on top
+    for _ in 0..4 {
+        for left in [0, 2, 4, 6] {
+            drop_piece(&mut game, TetrominoType::O, 0, left);
+        }
+    }
+    drop_piece(&mut game, TetrominoType::O, 0, 0);
+    drop_piece(&mut game, TetrominoType::I, 1, 8);
+    drop_piece(&mut game, TetrominoType::I, 1, 8);
+    assert!(!game.back_to_back());
+
+    // The first Tetris starts the streak, the second one gets half as much again (and a combo)
+    drop_piece(&mut game, TetrominoType::I, 1, 9);
+    assert_eq!(scored(&mut game)[0].points, 800);
+    assert!(game.back_to_back());
+    drop_piece(&mut game, TetrominoType::I, 1, 9);
+    let clears = scored(&mut game);
+    assert!(clears[0].back_to_back);
+    assert_eq!(clears[0].points, 800 * 3 / 2 + 50);
+    assert_eq!(clears[0].name(), "Back-to-Back Tetris");
+}
+
+#[test]
+fn clearing_everything_is_a_perfect_clear() {
+    let mut game = Game::new(Settings::default());
+
+    // Four rows with two columns left, filled by two I's on their sides
+    for _ in 0..2 {
+        for left in [0, 2, 4, 6] {
+            drop_piece(&mut game, TetrominoType::O, 0, left);
+        }
+    }
+    drop_piece(&mut game, TetrominoType::I, 1, 8);
+    assert!(scored(&mut game).is_empty());
+    drop_piece(&mut game, TetrominoType::I, 1, 9);
+
+    // A Tetris and the perfect clear bonus on top
+    let clears = scored(&mut game);
+    assert!(clears[0].perfect);
+    assert_eq!(clears[0].points, 800 + 2000);
+    assert!(game.board().is_empty());
+
+    let stats = game.stats();
+    assert_eq!(stats.pieces, 10);
+    assert_eq!(stats.lines, 4);
+    assert_eq!(stats.tetrises, 1);
+    assert_eq!(stats.perfect_clears, 1);
+}
@@ -0,0 +1,122 @@
+use tetris::config::GameConfig;
+use tetris::game::{Action, Event, Game, Settings};
+use tetris::replay::{self, Replay};
+use tetris::tetromino::TetrominoType;
+
+/// The sizes we want to work: the usual one, 4 wide training, a 6x12 mini board and a 12x30 big board
+const SIZES: [(i32, i32); 4] = [(10, 20), (4, 20), (6, 12), (12, 30)];
+
+/// A game on a grid this size, set up the way the configuration file would
+fn sized_game(width: i32, height: i32) -> Game {
+    let config = GameConfig {
+        field_width: width,
+        field_height: height,
+        ..GameConfig::default()
+    };
+    config.validate().unwrap();
+    Game::new(config.settings())
+}
+
+/// Is every block of the current tetromino inside the grid?
+fn inside(game: &Game) -> bool {
+    let board = game.board();
+    game.piece().unwrap().blocks.iter().all(|&(x, y)| board.contains(x, y))
+}
+
+#[test]
+fn start_in_the_middle_of_any_width() {
+    assert_eq!(Settings::default().start_pos, (4, 4));
+    assert_eq!(Settings::start_for(4), (0, 4));
+    assert_eq!(Settings::start_for(6), (2, 4));
+    assert_eq!(Settings::start_for(12), (5, 4));
+    assert_eq!(Settings::sized(12, 30).start_pos, (5, 4));
+
+    // A start that doesn't fit is refused rather than panicking later
+    let config = GameConfig {
+        field_width: 4,
+        start_pos: Some((4, 4)),
+        ..GameConfig::default()
+    };
+    assert!(config.validate().unwrap_err().to_string().starts_with("start_pos"));
+
+    // So is one with no room above the field for a tetromino 2 high, from a configuration or a replay
+    let config = GameConfig {
+        start_pos: Some((4, 1)),
+        ..GameConfig::default()
+    };
+    assert!(config.validate().unwrap_err().to_string().starts_with("start_pos"));
+    let header = format!("tetris-replay {}\nseed 1\nstart 4 1\n", replay::VERSION);
+    assert!(header.parse::<Replay>().is_err());
+}
+
+#[test]
+fn every_tetromino_fits_and_turns() {
+    for (width, height) in SIZES {
+        let mut game = sized_game(width, height);
+        assert_eq!(game.board().width(), width);
+        assert_eq!(game.board().height(), height + Settings::BUFFER_ROWS);
+
+        for tetromino_type in TetrominoType::ALL {
+            game.spawn(tetromino_type);
+            assert!(inside(&game), "{:?} on {}x{}", tetromino_type, width, height);
+
+            // Against either wall, it can still turn all the way round (kicking if it has to)
+            for action in [Action::MoveLeft, Action::MoveRight] {
+                while game.apply(action) {}
+                for _ in 0..4 {
+                    game.apply(Action::RotateClockwise);
+                    assert!(inside(&game), "{:?} on {}x{}", tetromino_type, width, height);
+                }
+            }
+            let rightmost = game.piece().unwrap().blocks.iter().map(|block| block.0).max().unwrap();
+            assert_eq!(rightmost, width - 1, "{:?} on {}x{}", tetromino_type, width, height);
+        }
+    }
+}
+
+#[test]
+fn lines_clear_at_the_bottom_of_any_size() {
+    for (width, height) in SIZES {
+        let mut game = sized_game(width, height);
+
+        // An I on its end in every column, each landing on the floor
+        for column in 0..width {
+            game.spawn(TetrominoType::I);
+            assert!(game.apply(Action::RotateClockwise));
+            while game.apply(Action::MoveLeft) {}
+            for _ in 0..column {
+                assert!(game.apply(Action::MoveRight));
+            }
+            let lowest = game.ghost().unwrap().iter().map(|block| block.1).max().unwrap();
+            assert_eq!(lowest, height + Settings::BUFFER_ROWS - 1, "{}x{}", width, height);
+            game.take_events();
+            assert!(game.apply(Action::HardDrop));
+        }
+
+        // The last one clears all four rows, leaving nothing
+        let cleared = game.take_events().into_iter().find_map(|event| match event {
+            Event::LinesCleared(rows) => Some(rows.len()),
+            _ => None,
+        });
+        assert_eq!(cleared, Some(4), "{}x{}", width, height);
+        assert!(game.board().is_empty());
+    }
+}
+
+#[test]
+fn game_over_at_the_top_of_any_size() {
+    for (width, height) in SIZES {
+        let mut game = sized_game(width, height);
+
+        // O's on top of each other in the first column, until one locks above the field
+        let mut pieces = 0;
+        while !game.is_game_over() {
+            game.spawn(TetrominoType::O);
+            while game.apply(Action::MoveLeft) {}
+            game.apply(Action::HardDrop);
+            pieces += 1;
+            assert!(pieces <= height, "{}x{}", width, height);
+        }
+        assert_eq!(pieces, height / 2 + 1, "{}x{}", width, height);
+    }
+}
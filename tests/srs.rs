@@ -0,0 +1,125 @@
+use tetris::game::{Action, Game, Settings};
+use tetris::srs::{self, Rotation};
+use tetris::tetromino::TetrominoType;
+
+/// The blocks of the current tetromino, sorted so they can be compared
+fn blocks(game: &Game) -> Vec<(i32, i32)> {
+    let mut blocks = game.piece().unwrap().blocks.to_vec();
+    blocks.sort_unstable();
+    blocks
+}
+
+#[test]
+fn kick_tables_match_the_guideline() {
+    assert_eq!(
+        srs::kicks(TetrominoType::T, Rotation::Zero, Rotation::Right),
+        Some([(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)])
+    );
+    assert_eq!(
+        srs::kicks(TetrominoType::J, Rotation::Left, Rotation::Zero),
+        Some([(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)])
+    );
+    assert_eq!(
+        srs::kicks(TetrominoType::I, Rotation::Zero, Rotation::Right),
+        Some([(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)])
+    );
+    assert_eq!(
+        srs::kicks(TetrominoType::I, Rotation::Two, Rotation::Left),
+        Some([(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)])
+    );
+    assert_eq!(srs::kicks(TetrominoType::O, Rotation::Right, Rotation::Two), Some([(0, 0); 5]));
+    assert_eq!(srs::kicks(TetrominoType::T, Rotation::Zero, Rotation::Two), None);
+}
+
+#[test]
+fn reverse_kicks_undo_each_other() {
+    // Every kick from A to B is the negative of the same kick from B to A
+    for &(from, to) in srs::TRANSITIONS.iter() {
+        for tetromino_type in TetrominoType::ALL {
+            let there = srs::kicks(tetromino_type, from, to).unwrap();
+            let back = srs::kicks(tetromino_type, to, from).unwrap();
+            for (a, b) in there.iter().zip(back.iter()) {
+                assert_eq!((a.0, a.1), (-b.0, -b.1));
+            }
+        }
+    }
+}
+
+#[test]
+fn four_rotations_return_to_spawn() {
+    for tetromino_type in TetrominoType::ALL {
+        let mut rotation = Rotation::Zero;
+        for _ in 0..4 {
+            rotation = rotation.clockwise();
+        }
+        assert_eq!(rotation, Rotation::Zero);
+        assert_eq!(tetromino_type.blocks_in(Rotation::Left), {
+            let mut blocks = tetromino_type.blocks();
+            for _ in 0..3 {
+                blocks = blocks.map(|(x, y)| (tetromino_type.size() - 1 - y, x));
+            }
+            blocks
+        });
+    }
+}
+
+#[test]
+fn t_rotation_states() {
+    // Spawn state points up, R points right
+    let mut up = TetrominoType::T.blocks_in(Rotation::Zero).to_vec();
+    up.sort_unstable();
+    assert_eq!(up, vec![(0, 1), (1, 0), (1, 1), (2, 1)]);
+
+    let mut right = TetrominoType::T.blocks_in(Rotation::Right).to_vec();
+    right.sort_unstable();
+    assert_eq!(right, vec![(1, 0), (1, 1), (1, 2), (2, 1)]);
+
+    let mut left = TetrominoType::T.blocks_in(Rotation::Left).to_vec();
+    left.sort_unstable();
+    assert_eq!(left, vec![(0, 1), (1, 0), (1, 1), (1, 2)]);
+}
+
+#[test]
+fn i_kicks_off_the_right_wall() {
+    let mut game = Game::new(Settings::default());
+    game.spawn(TetrominoType::I);
+
+    // Stand it up and push it against the right wall
+    assert!(game.apply(Action::RotateClockwise));
+    while game.apply(Action::MoveRight) {}
+    assert!(blocks(&game).iter().all(|&(x, _y)| x == 9));
+
+    // R -> 2 would put it in columns 7..10, the first kick (-1, 0) moves it back inside
+    assert!(game.apply(Action::RotateClockwise));
+    let piece = game.piece().unwrap();
+    assert_eq!(piece.rotation, Rotation::Two);
+    assert_eq!(blocks(&game), vec![(6, 4), (7, 4), (8, 4), (9, 4)]);
+}
+
+#[test]
+fn t_kicks_off_the_floor() {
+    let mut game = Game::new(Settings::default());
+    game.spawn(TetrominoType::T);
+
+    // Down to the floor, where it waits for the lock delay
+    for _ in 0..20 {
+        game.apply(Action::SoftDrop);
+    }
+    assert_eq!(blocks(&game), vec![(4, 23), (5, 22), (5, 23), (6, 23)]);
+
+    // 0 -> R needs the row below the floor; (-1, 0) doesn't help, (-1, +1) lifts it up a row
+    assert!(game.apply(Action::RotateClockwise));
+    let piece = game.piece().unwrap();
+    assert_eq!(piece.rotation, Rotation::Right);
+    assert_eq!(blocks(&game), vec![(4, 21), (4, 22), (4, 23), (5, 22)]);
+}
+
+#[test]
+fn o_does_not_move_when_rotated() {
+    let mut game = Game::new(Settings::default());
+    game.spawn(TetrominoType::O);
+    let before = blocks(&game);
+
+    assert!(game.apply(Action::RotateClockwise));
+    assert_eq!(blocks(&game), before);
+}